@@ -1,55 +1,338 @@
-// Import the WebAssembly memory at the top of the file.
-import { memory } from "wasm-app/wasm_app_bg";
-import { Game } from "wasm-app";
+import { Game, GameConfig } from "wasm-app";
+import { createWorkerPlayer } from "./worker-player.js";
 
+// Keyboard input goes to the player last clicked or started.
+let activePlayer = null;
 
-const romInput = document.getElementById("rom-file");
-const startButton = document.getElementById("start-game");
-const romReader = new FileReader();
+// Every element with the `chip8-player` class gets its own controls, canvas
+// and emulator, so several games can share the page.
+const createPlayer = (root) => {
+    const romInput = root.querySelector(".rom-file");
+    const startButton = root.querySelector(".start-game");
+    const romReader = new FileReader();
 
-let game = null;
-let romBytes = null;
-let globalReloadFlag = null;
-let pressedKey = null;
+    const layoutSelect = root.querySelector(".keymap-layout");
+    const keymapInput = root.querySelector(".keymap-file");
+    const keymapReader = new FileReader();
 
-const sleep = (milliseconds) => {
-    return new Promise(resolve => setTimeout(resolve, milliseconds))
-}
+    const canvas = root.querySelector(".game-canvas");
+
+    // Phones and tablets get the on-screen keypad unless unticked.
+    const keypadCheckbox = root.querySelector(".virtual-keypad");
+    keypadCheckbox.checked = window.matchMedia("(pointer: coarse)").matches;
+
+    const profileSelect = root.querySelector(".profile");
+    const speedInput = root.querySelector(".instructions-per-frame");
+    const foregroundInput = root.querySelector(".foreground");
+    const backgroundInput = root.querySelector(".background");
+
+    const volumeInput = root.querySelector(".sound-volume");
+    const frequencyInput = root.querySelector(".sound-frequency");
+
+    const slotInput = root.querySelector(".save-slot");
+    const saveSlotButton = root.querySelector(".save-slot-button");
+    const loadSlotButton = root.querySelector(".load-slot-button");
+    const exportButton = root.querySelector(".export-state");
+    const importInput = root.querySelector(".import-state");
+    const importReader = new FileReader();
+
+    const debuggerPanel = root.querySelector(".debugger");
+    const debugPauseButton = root.querySelector(".debug-pause");
+    const debugStepButton = root.querySelector(".debug-step");
+    const debugState = root.querySelector(".debug-state");
+    const debugProgram = root.querySelector(".debug-program");
+
+    const player = {
+        game: null,
+        keymapConfig: null,
+        runningGame: null,
+        reloadFlag: null,
+        setKeyHeld: (keyName, held) =>
+            player.runningGame !== null && player.runningGame.set_key_held(keyName, held),
+        resumeAudio: () => {
+            if (player.runningGame !== null) {
+                player.runningGame.resume_audio();
+            }
+        },
+    };
 
-const runGame = async (runningGame) => {
-    const localReloadFlag = globalReloadFlag = new Object();
+    const stop = () => {
+        player.reloadFlag = new Object();
+        if (player.runningGame !== null) {
+            player.runningGame.free();
+            player.runningGame = null;
+        }
+    }
+
+    // Driven by the display refresh, the emulator works out how many 60 Hz
+    // frames the time since the previous callback amounts to.
+    const runGame = (startedGame) => {
+        stop();
+        const localReloadFlag = player.reloadFlag;
+        let previousTimestamp = null;
+
+        player.runningGame = startedGame;
+
+        const renderLoop = (timestamp) => {
+            if (localReloadFlag !== player.reloadFlag) {
+                return;
+            }
 
-    const pressedKeyPtr = runningGame.get_pressed_key_ptr();
-    pressedKey = new Uint8Array(memory.buffer, pressedKeyPtr, 0x1);
+            const elapsed = previousTimestamp === null ? 0 : timestamp - previousTimestamp;
+            previousTimestamp = timestamp;
 
-    while (runningGame.run_cycle()) {
-        if (localReloadFlag !== globalReloadFlag) {
+            if (!player.runningGame.run_frame(elapsed)) {
+                stop();
+                return;
+            }
+            showDebugger();
+            requestAnimationFrame(renderLoop);
+        };
+
+        requestAnimationFrame(renderLoop);
+    }
+
+    const hex = (value, digits) => value.toString(16).toUpperCase().padStart(digits, "0");
+
+    // Clicking an instruction sets or clears a breakpoint on it.
+    let shownProgram = null;
+    const showDebugger = () => {
+        if (!debuggerPanel.open || player.runningGame === null) {
             return;
         }
-        await sleep(2);
-    };
-}
 
-romInput.addEventListener("change", event => {
-    romReader.readAsArrayBuffer(romInput.files[0]);
-})
+        const game = player.runningGame;
+        const registers = Array.from(game.get_registers(), (value, index) =>
+            `V${hex(index, 1)} ${hex(value, 2)}`
+        );
+        const stack = Array.from(game.get_stack(), frame => hex(frame, 3));
+        debugState.textContent = [
+            `PC ${hex(game.get_program_counter(), 3)}  I ${hex(game.get_address_register(), 3)}` +
+                `  DT ${hex(game.get_delay_timer(), 2)}  ST ${hex(game.get_sound_timer(), 2)}` +
+                (game.is_paused() ? "  PAUSED" : ""),
+            ...[0, 4, 8, 12].map(row => registers.slice(row, row + 4).join("  ")),
+            `Stack ${stack.reverse().join(" ")}`,
+        ].join("\n");
 
-romReader.addEventListener("load", event => {
-    game = Game.new();
-    const romPtr = game.get_rom_ptr();
-    romBytes = new Uint8Array(memory.buffer, romPtr, 0xe00);
-    romBytes.set(new Uint8Array(romReader.result));
-});
+        // Lines are only replaced when they change, otherwise a click would
+        // start on one line and end on its replacement.
+        const lines = game.disassemble(9).map(instruction => {
+            const line = {
+                address: instruction.address,
+                text: (instruction.breakpoint ? "*" : " ") + (instruction.current ? ">" : " ") +
+                    `${hex(instruction.address, 3)} ${hex(instruction.opcode, 4)} ${instruction.text}`,
+            };
+            instruction.free();
+            return line;
+        });
+        const program = lines.map(line => line.text).join("\n");
+        if (program !== shownProgram) {
+            shownProgram = program;
+            debugProgram.replaceChildren(...lines.map(({ address, text }) => {
+                const line = document.createElement("div");
+                line.dataset.address = address;
+                line.textContent = text;
+                return line;
+            }));
+        }
+    }
+
+    debugProgram.addEventListener("click", event => {
+        const line = event.target.closest("[data-address]");
+        if (line !== null && player.runningGame !== null) {
+            player.runningGame.toggle_breakpoint(parseInt(line.dataset.address));
+            showDebugger();
+        }
+    });
+
+    debuggerPanel.addEventListener("toggle", showDebugger);
+
+    debugPauseButton.addEventListener("click", event => {
+        if (player.runningGame !== null) {
+            player.runningGame.set_paused(!player.runningGame.is_paused());
+            showDebugger();
+        }
+    });
+
+    debugStepButton.addEventListener("click", event => {
+        if (player.runningGame !== null) {
+            player.runningGame.step();
+            showDebugger();
+        }
+    });
+
+    romInput.addEventListener("change", event => {
+        romReader.readAsArrayBuffer(romInput.files[0]);
+    })
+
+    romReader.addEventListener("load", event => {
+        const game = Game.new();
+        try {
+            game.load_rom(new Uint8Array(romReader.result), romInput.files[0].name);
+        } catch (error) {
+            game.free();
+            alert(`Cannot load the ROM, ${error}`);
+            return;
+        }
+
+        if (player.game !== null) {
+            player.game.free();
+        }
+        player.game = game;
+    });
+
+    keymapInput.addEventListener("change", event => {
+        keymapReader.readAsText(keymapInput.files[0]);
+    });
+
+    keymapReader.addEventListener("load", event => {
+        player.keymapConfig = keymapReader.result;
+    });
+
+    layoutSelect.addEventListener("change", event => {
+        player.keymapConfig = null;
+    });
+
+    // Settings are read from the panel every time a game starts.
+    const applyConfig = () => {
+        const config = GameConfig.new();
+        try {
+            config.set_profile(profileSelect.value);
+            config.set_instructions_per_frame(parseInt(speedInput.value));
+            config.set_foreground(foregroundInput.value);
+            config.set_background(backgroundInput.value);
+            if (player.keymapConfig === null) {
+                config.set_keymap_layout(layoutSelect.value);
+            } else {
+                config.set_keymap_config(player.keymapConfig);
+            }
+            player.game.set_config(config);
+        } finally {
+            config.free();
+        }
+    }
+
+    const applySound = (target) => {
+        target.set_sound_volume(parseFloat(volumeInput.value));
+        target.set_sound_frequency(parseFloat(frequencyInput.value));
+    }
+
+    volumeInput.addEventListener("input", event => {
+        if (player.runningGame !== null) {
+            applySound(player.runningGame);
+        }
+    });
+
+    frequencyInput.addEventListener("change", event => {
+        if (player.runningGame !== null) {
+            applySound(player.runningGame);
+        }
+    });
+
+    // Slots live in localStorage under a hash of the ROM, so each game has
+    // its own set.
+    const withRunningGame = (action, description) => {
+        if (player.runningGame === null) {
+            alert("Start a game first");
+            return;
+        }
+
+        try {
+            action(player.runningGame);
+        } catch (error) {
+            alert(`Cannot ${description}, ${error}`);
+        }
+    }
 
-startButton.addEventListener("click", event => {
-    globalReloadFlag = new Object();
-    runGame(game.start());
+    saveSlotButton.addEventListener("click", event => {
+        withRunningGame(game => game.save_slot(parseInt(slotInput.value)), "save");
+    });
+
+    loadSlotButton.addEventListener("click", event => {
+        withRunningGame(game => {
+            if (!game.load_slot(parseInt(slotInput.value))) {
+                alert(`Slot ${slotInput.value} is empty`);
+            }
+        }, "load");
+    });
+
+    exportButton.addEventListener("click", event => {
+        withRunningGame(game => {
+            const link = document.createElement("a");
+            link.href = URL.createObjectURL(new Blob([game.export_state()]));
+            link.download = "chip8.state";
+            link.click();
+            URL.revokeObjectURL(link.href);
+        }, "export the state");
+    });
+
+    importInput.addEventListener("change", event => {
+        importReader.readAsArrayBuffer(importInput.files[0]);
+    });
+
+    importReader.addEventListener("load", event => {
+        withRunningGame(
+            game => game.import_state(new Uint8Array(importReader.result)),
+            "import the state"
+        );
+    });
+
+    root.addEventListener("pointerdown", event => {
+        activePlayer = player;
+    });
+
+    startButton.addEventListener("click", event => {
+        if (player.game === null) {
+            alert("Pick a ROM first");
+            return;
+        }
+
+        try {
+            applyConfig();
+            applySound(player.game);
+            player.game.set_canvas(canvas);
+            player.game.set_virtual_keypad(keypadCheckbox.checked);
+            runGame(player.game.start());
+            activePlayer = player;
+        } catch (error) {
+            alert(`Cannot start the game, ${error}`);
+        }
+    });
+
+    return player;
+}
+
+// Players marked with `data-worker` run their emulator in a Web Worker when
+// the browser supports OffscreenCanvas.
+document.querySelectorAll(".chip8-player").forEach(root => {
+    const canvas = root.querySelector(".game-canvas");
+    if (root.dataset.worker !== undefined && canvas.transferControlToOffscreen !== undefined) {
+        createWorkerPlayer(root, player => activePlayer = player);
+    } else {
+        createPlayer(root);
+    }
 });
 
+// Browsers only let audio play once the page got a user gesture.
+const resumeAudio = () => {
+    if (activePlayer !== null) {
+        activePlayer.resumeAudio();
+    }
+}
+
+document.addEventListener("pointerdown", resumeAudio);
+
 document.addEventListener("keydown", event => {
-    pressedKey.set([event.keyCode]);
+    resumeAudio();
+    const keyName = event.ctrlKey ? `ctrl-${event.key}` : event.key;
+    if (activePlayer !== null && activePlayer.setKeyHeld(keyName, true)) {
+        event.preventDefault();
+    }
 });
 
+// Named as on keydown so that a held Ctrl-L is released too.
 document.addEventListener("keyup", event => {
-    pressedKey.set([0]);
+    if (activePlayer !== null) {
+        activePlayer.setKeyHeld(event.ctrlKey ? `ctrl-${event.key}` : event.key, false);
+    }
 })
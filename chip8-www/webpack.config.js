@@ -1,14 +1,26 @@
 const CopyWebpackPlugin = require("copy-webpack-plugin");
 const path = require('path');
 
-module.exports = {
-  entry: "./bootstrap.js",
-  output: {
-    path: path.resolve(__dirname, "dist"),
-    filename: "bootstrap.js",
+module.exports = [
+  {
+    entry: "./bootstrap.js",
+    output: {
+      path: path.resolve(__dirname, "dist"),
+      filename: "bootstrap.js",
+    },
+    mode: "development",
+    plugins: [
+      new CopyWebpackPlugin(['index.html'])
+    ],
   },
-  mode: "development",
-  plugins: [
-    new CopyWebpackPlugin(['index.html'])
-  ],
-};
+  // The emulator worker loads its own copy of the wasm module.
+  {
+    entry: "./worker.js",
+    target: "webworker",
+    output: {
+      path: path.resolve(__dirname, "dist"),
+      filename: "worker.js",
+    },
+    mode: "development",
+  },
+];
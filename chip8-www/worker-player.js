@@ -0,0 +1,139 @@
+import {
+    Beeper,
+    WorkerEventKind,
+    WorkerKeymap,
+    decode_worker_event,
+    encode_key,
+    encode_keymap_config,
+    encode_keymap_layout,
+    encode_load_rom,
+    encode_start,
+} from "wasm-app";
+
+// A player whose emulator runs in a worker, drawing to the canvas through
+// an OffscreenCanvas. The canvas belongs to the worker for good once handed
+// over, so such a player never falls back to the main thread.
+export const createWorkerPlayer = (root, activate) => {
+    const romInput = root.querySelector(".rom-file");
+    const startButton = root.querySelector(".start-game");
+    const layoutSelect = root.querySelector(".keymap-layout");
+    const keymapInput = root.querySelector(".keymap-file");
+    const volumeInput = root.querySelector(".sound-volume");
+    const frequencyInput = root.querySelector(".sound-frequency");
+    const canvas = root.querySelector(".game-canvas");
+
+    const offscreenCanvas = canvas.transferControlToOffscreen();
+    const worker = new Worker("./worker.js");
+    worker.postMessage({ canvas: offscreenCanvas }, [offscreenCanvas]);
+
+    const post = (message) => worker.postMessage(message, [message.buffer]);
+
+    let beeper = null;
+    let keymapConfig = null;
+    let keymap = null;
+    let romName = null;
+    let running = false;
+
+    // Only keys the game uses are taken from the browser and sent over.
+    const player = {
+        setKeyHeld: (keyName, held) => {
+            if (!running || keymap === null || !keymap.is_bound(keyName)) {
+                return false;
+            }
+            post(encode_key(keyName, held));
+            return true;
+        },
+        resumeAudio: () => {
+            if (beeper !== null) {
+                beeper.resume_audio();
+            }
+        },
+    };
+
+    const applySound = () => {
+        if (beeper !== null) {
+            beeper.set_sound_volume(parseFloat(volumeInput.value));
+            beeper.set_sound_frequency(parseFloat(frequencyInput.value));
+        }
+    }
+
+    worker.addEventListener("message", event => {
+        const workerEvent = decode_worker_event(event.data);
+        switch (workerEvent.kind) {
+            case WorkerEventKind.Started:
+                running = true;
+                break;
+            case WorkerEventKind.Sound:
+                if (beeper !== null) {
+                    beeper.set_playing(workerEvent.sound_on);
+                }
+                break;
+            case WorkerEventKind.Halted:
+                running = false;
+                break;
+            case WorkerEventKind.Error:
+                alert(`The game failed, ${workerEvent.text}`);
+                break;
+        }
+        workerEvent.free();
+    });
+
+    romInput.addEventListener("change", event => {
+        const file = romInput.files[0];
+        file.arrayBuffer().then(buffer => {
+            post(encode_load_rom(file.name, new Uint8Array(buffer)));
+            romName = file.name;
+        });
+    });
+
+    keymapInput.addEventListener("change", event => {
+        keymapInput.files[0].text().then(config => keymapConfig = config);
+    });
+
+    layoutSelect.addEventListener("change", event => {
+        keymapConfig = null;
+    });
+
+    volumeInput.addEventListener("input", applySound);
+    frequencyInput.addEventListener("change", applySound);
+
+    root.addEventListener("pointerdown", event => activate(player));
+
+    startButton.addEventListener("click", event => {
+        if (romName === null) {
+            alert("Pick a ROM first");
+            return;
+        }
+
+        if (beeper === null) {
+            try {
+                beeper = Beeper.new(parseFloat(frequencyInput.value), parseFloat(volumeInput.value));
+            } catch (error) {
+                // Without Web Audio the game simply runs silent.
+            }
+        }
+        applySound();
+
+        let startKeymap;
+        try {
+            startKeymap = keymapConfig === null
+                ? WorkerKeymap.from_layout(layoutSelect.value)
+                : WorkerKeymap.from_config(keymapConfig, romName);
+        } catch (error) {
+            alert(`Cannot start the game, ${error}`);
+            return;
+        }
+        if (keymap !== null) {
+            keymap.free();
+        }
+        keymap = startKeymap;
+
+        post(keymapConfig === null
+            ? encode_keymap_layout(layoutSelect.value)
+            : encode_keymap_config(keymapConfig));
+        post(encode_start());
+        activate(player);
+    });
+
+    return player;
+}
@@ -0,0 +1,49 @@
+// Runs a game off the main thread. The first message carries the
+// OffscreenCanvas to draw to, every later one is a request encoded by the
+// `encode_*` functions of wasm-app. Events go back the same way.
+const pending = [];
+let host = null;
+
+self.addEventListener("message", event => {
+    pending.push(event.data);
+    if (host !== null) {
+        drain();
+    }
+});
+
+const postEvents = () => {
+    let message;
+    while ((message = host.poll_event()) !== undefined) {
+        self.postMessage(message, [message.buffer]);
+    }
+}
+
+const drain = () => {
+    while (pending.length > 0) {
+        host.handle_message(pending.shift());
+    }
+    postEvents();
+}
+
+// Workers only have requestAnimationFrame where they can draw to an
+// OffscreenCanvas; elsewhere a timer close to 60 Hz will do.
+const nextFrame = self.requestAnimationFrame !== undefined
+    ? callback => self.requestAnimationFrame(callback)
+    : callback => self.setTimeout(() => callback(performance.now()), 1000 / 60);
+
+import("wasm-app").then(({ WorkerHost }) => {
+    host = WorkerHost.new(pending.shift().canvas);
+    drain();
+
+    let previousTimestamp = null;
+    const renderLoop = (timestamp) => {
+        const elapsed = previousTimestamp === null ? 0 : timestamp - previousTimestamp;
+        previousTimestamp = timestamp;
+
+        host.run_frame(elapsed);
+        postEvents();
+        nextFrame(renderLoop);
+    };
+
+    nextFrame(renderLoop);
+});
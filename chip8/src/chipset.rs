@@ -1,11 +1,192 @@
 use crate::display::GraphicDisplay;
-use crate::gpu::Gpu;
-use crate::keyboard::Keyboard;
-use crate::memory::{Memory, Registers, Stack, MEMORY_SIZE};
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::gpu::{Gpu, GraphicMemory};
+use crate::host_event::HostEvent;
+use crate::keyboard::{Key, Keyboard, Keypad};
+use crate::memory::{Memory, Registers, Stack, MEMORY_SIZE, REGISTERS_COUNT, STACK_SIZE};
 use crate::opcode_processor::{OpCode, OpCodesProcessor};
+use std::cmp;
+use std::collections::BTreeSet;
+use std::fmt;
 
 pub const PROGRAM_COUNTER_BOUNDARY: u16 = 0x200;
 pub const INSTRUCTION_SIZE: u16 = 2;
+pub const PERSISTENT_FLAGS_COUNT: usize = 8;
+
+/// The SUPER-CHIP RPL flags written by FX75, which outlive the program so
+/// hosts can store them between runs.
+pub type PersistentFlags = [u8; PERSISTENT_FLAGS_COUNT];
+
+const STATE_MAGIC: &[u8; 4] = b"C8S\x01";
+const STATE_SIZE: usize = STATE_MAGIC.len()
+    + MEMORY_SIZE
+    + REGISTERS_COUNT
+    + STACK_SIZE * 2
+    + 1
+    + 6
+    + 3
+    + DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HaltReason {
+    Quit,
+    EndOfProgram,
+    ProgramCounterOutOfMemory(u16),
+    UnknownOpCode(u16),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ChipsetState {
+    Running,
+    WaitingForKey { x: u8, pressed_key: Option<Key> },
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CycleOutcome {
+    Executed,
+    Drew,
+    WaitingForKey,
+    SoundStarted,
+    SoundStopped,
+    Halted(HaltReason),
+    BreakpointHit,
+    Paused,
+}
+
+/// Snapshot of everything a running program can observe, used for save states.
+#[derive(Clone, PartialEq)]
+pub struct MachineState {
+    memory: Memory,
+    registers: Registers,
+    stack: Stack,
+    address_register: u16,
+    program_counter: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    state: ChipsetState,
+    graphic_memory: GraphicMemory,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum StateError {
+    InvalidHeader,
+    InvalidLength(usize),
+    InvalidValue,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidHeader => write!(formatter, "not a save state"),
+            StateError::InvalidLength(length) => write!(
+                formatter,
+                "save state is {} bytes long, expected {}",
+                length, STATE_SIZE
+            ),
+            StateError::InvalidValue => write!(formatter, "save state is corrupted"),
+        }
+    }
+}
+
+impl MachineState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATE_SIZE);
+        bytes.extend_from_slice(STATE_MAGIC);
+        bytes.extend((0..MEMORY_SIZE).map(|address| self.memory.read(address as u16)));
+        bytes.extend((0..REGISTERS_COUNT).map(|index| self.registers.get_register_at(index)));
+        for frame in self.stack.get_frames() {
+            bytes.extend_from_slice(&frame.to_be_bytes());
+        }
+        bytes.push(self.stack.get_stack_pointer() as u8);
+        bytes.extend_from_slice(&self.address_register.to_be_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_be_bytes());
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        match self.state {
+            ChipsetState::Running => bytes.extend_from_slice(&[0, 0, 0]),
+            ChipsetState::WaitingForKey { x, pressed_key } => {
+                bytes.extend_from_slice(&[1, x, pressed_key.map_or(0xff, |key| key as u8)])
+            }
+        }
+        for y in 0..DISPLAY_HEIGHT {
+            bytes.extend_from_slice(&self.graphic_memory[y]);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<MachineState, StateError> {
+        if !bytes.starts_with(STATE_MAGIC) {
+            return Err(StateError::InvalidHeader);
+        }
+        if bytes.len() != STATE_SIZE {
+            return Err(StateError::InvalidLength(bytes.len()));
+        }
+
+        let (memory_bytes, rest) = bytes[STATE_MAGIC.len()..].split_at(MEMORY_SIZE);
+        let mut memory = Memory::new();
+        for (address, byte) in memory_bytes.iter().enumerate() {
+            memory.write(address as u16, *byte);
+        }
+
+        let (register_bytes, rest) = rest.split_at(REGISTERS_COUNT);
+        let mut registers = Registers::new();
+        for (index, byte) in register_bytes.iter().enumerate() {
+            registers.set_register_at(index, *byte);
+        }
+
+        let (stack_bytes, rest) = rest.split_at(STACK_SIZE * 2);
+        let frames = stack_bytes
+            .chunks(2)
+            .map(|frame| u16::from_be_bytes([frame[0], frame[1]]))
+            .collect::<Vec<u16>>();
+        let stack_pointer = rest[0] as usize;
+        if stack_pointer > STACK_SIZE {
+            return Err(StateError::InvalidValue);
+        }
+        let mut stack = Stack::new();
+        stack.restore(&frames, stack_pointer);
+
+        let state = match rest[7] {
+            0 => ChipsetState::Running,
+            1 if rest[8] < 0x10 => ChipsetState::WaitingForKey {
+                x: rest[8],
+                pressed_key: Key::from_index(rest[9]),
+            },
+            _ => return Err(StateError::InvalidValue),
+        };
+
+        let mut graphic_memory = GraphicMemory::new();
+        for (y, row) in rest[10..].chunks(DISPLAY_WIDTH).enumerate() {
+            graphic_memory[y].copy_from_slice(row);
+        }
+
+        Ok(MachineState {
+            memory,
+            registers,
+            stack,
+            address_register: u16::from_be_bytes([rest[1], rest[2]]),
+            program_counter: u16::from_be_bytes([rest[3], rest[4]]),
+            delay_timer: rest[5],
+            sound_timer: rest[6],
+            state,
+            graphic_memory,
+        })
+    }
+}
+
+/// Registers and timers as shown by debuggers.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DebugState {
+    pub registers: [u8; REGISTERS_COUNT],
+    pub address_register: u16,
+    pub program_counter: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Return addresses, innermost call last.
+    pub stack: Vec<u16>,
+    pub waiting_for_key: bool,
+}
 
 pub trait RandomByteGenerator {
     fn generate(&self) -> u8;
@@ -26,10 +207,16 @@ pub struct Chip8Chipset<
     opcode_processor: O,
     gpu: G,
     keyboard: K,
+    keypad: Keypad,
     delay_timer: u8,
     sound_timer: u8,
     display: D,
     random_byte_generator: R,
+    state: ChipsetState,
+    halt_reason: Option<HaltReason>,
+    breakpoints: BTreeSet<u16>,
+    resumed_breakpoint: Option<u16>,
+    persistent_flags: PersistentFlags,
 }
 
 impl<O: OpCodesProcessor, G: Gpu, K: Keyboard, D: GraphicDisplay, R: RandomByteGenerator>
@@ -54,10 +241,16 @@ impl<O: OpCodesProcessor, G: Gpu, K: Keyboard, D: GraphicDisplay, R: RandomByteG
             opcode_processor,
             gpu,
             keyboard,
+            keypad: Keypad::new(),
             delay_timer: 0,
             sound_timer: 0,
             display,
             random_byte_generator,
+            state: ChipsetState::Running,
+            halt_reason: None,
+            breakpoints: BTreeSet::new(),
+            resumed_breakpoint: None,
+            persistent_flags: [0; PERSISTENT_FLAGS_COUNT],
         }
     }
 
@@ -65,271 +258,532 @@ impl<O: OpCodesProcessor, G: Gpu, K: Keyboard, D: GraphicDisplay, R: RandomByteG
         &self.keyboard
     }
 
-    pub fn tick(&mut self) -> Result<(), String> {
-        let mut skip_instruction = false;
+    pub fn get_keyboard_mut(&mut self) -> &mut K {
+        &mut self.keyboard
+    }
+
+    pub fn get_display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn get_display(&self) -> &D {
+        &self.display
+    }
+
+    pub fn poll_host_event(&mut self) -> Option<HostEvent> {
+        self.keyboard.poll_host_event()
+    }
 
+    pub fn save_state(&self) -> MachineState {
+        MachineState {
+            memory: self.memory.clone(),
+            registers: self.registers.clone(),
+            stack: self.stack.clone(),
+            address_register: self.address_register,
+            program_counter: self.program_counter,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            state: self.state,
+            graphic_memory: self.gpu.get_memory().clone(),
+        }
+    }
+
+    pub fn load_state(&mut self, machine_state: MachineState) {
+        self.memory = machine_state.memory;
+        self.registers = machine_state.registers;
+        self.stack = machine_state.stack;
+        self.address_register = machine_state.address_register;
+        self.program_counter = machine_state.program_counter;
+        self.delay_timer = machine_state.delay_timer;
+        self.sound_timer = machine_state.sound_timer;
+        self.state = machine_state.state;
+        self.gpu.load_memory(&machine_state.graphic_memory);
+        self.halt_reason = None;
+        self.resumed_breakpoint = None;
+    }
+
+    /// Repaints the whole screen on the next present, e.g. after the host
+    /// display got garbled.
+    pub fn invalidate_display(&mut self) {
+        self.display.invalidate();
+        self.gpu.invalidate();
+    }
+
+    pub fn reset(&mut self, memory: Memory) {
+        self.memory = memory;
+        self.registers = Registers::new();
+        self.stack = Stack::new();
+        self.address_register = 0;
+        self.program_counter = PROGRAM_COUNTER_BOUNDARY;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.state = ChipsetState::Running;
+        self.keypad = Keypad::new();
+        self.gpu.clear();
+        self.halt_reason = None;
+        self.resumed_breakpoint = None;
+    }
+
+    pub fn get_debug_state(&self) -> DebugState {
+        let mut registers = [0; REGISTERS_COUNT];
+        for (index, register) in registers.iter_mut().enumerate() {
+            *register = self.registers.get_register_at(index);
+        }
+
+        DebugState {
+            registers,
+            address_register: self.address_register,
+            program_counter: self.program_counter,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            stack: self.stack.get_frames()[..self.stack.get_stack_pointer()].to_vec(),
+            waiting_for_key: self.is_waiting_for_key(),
+        }
+    }
+
+    pub fn get_persistent_flags(&self) -> &PersistentFlags {
+        &self.persistent_flags
+    }
+
+    pub fn set_persistent_flags(&mut self, persistent_flags: PersistentFlags) {
+        self.persistent_flags = persistent_flags;
+    }
+
+    /// Reads up to `length` bytes from `address`, stopping at the end of memory.
+    pub fn read_memory(&self, address: u16, length: usize) -> Vec<u8> {
+        let end = cmp::min(address as usize + length, MEMORY_SIZE);
+
+        (address as usize..end)
+            .map(|address| self.memory.read(address as u16))
+            .collect()
+    }
+
+    pub fn get_halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.state != ChipsetState::Running
+    }
+
+    pub fn is_sound_on(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address) {
+            return false;
+        }
+
+        self.breakpoints.insert(address)
+    }
+
+    pub fn get_breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// Runs a single cycle: ticks the timers, executes one instruction and
+    /// presents the framebuffer if the instruction changed it.
+    pub fn tick(&mut self) -> CycleOutcome {
+        if let Some(outcome) = self.check_stop() {
+            return outcome;
+        }
+
+        let sound_was_on = self.is_sound_on();
+        self.tick_timers();
+        let outcome = self.execute_instruction();
+        self.present();
+
+        self.with_sound_transition(outcome, sound_was_on)
+    }
+
+    /// Executes a single instruction without touching the timers or the display.
+    pub fn step(&mut self) -> CycleOutcome {
+        if let Some(outcome) = self.check_stop() {
+            return outcome;
+        }
+
+        let sound_was_on = self.is_sound_on();
+        let outcome = self.execute_instruction();
+
+        self.with_sound_transition(outcome, sound_was_on)
+    }
+
+    fn check_stop(&mut self) -> Option<CycleOutcome> {
+        if let Some(halt_reason) = self.halt_reason {
+            return Some(CycleOutcome::Halted(halt_reason));
+        }
+
+        if self.is_waiting_for_key() {
+            return None;
+        }
+
+        if self.breakpoints.contains(&self.program_counter)
+            && self.resumed_breakpoint != Some(self.program_counter)
+        {
+            self.resumed_breakpoint = Some(self.program_counter);
+            return Some(CycleOutcome::BreakpointHit);
+        }
+        self.resumed_breakpoint = None;
+
+        None
+    }
+
+    /// `Drew` wins over a sound change in the same cycle, callers tracking the
+    /// sound also check `is_sound_on`.
+    fn with_sound_transition(&self, outcome: CycleOutcome, sound_was_on: bool) -> CycleOutcome {
+        match outcome {
+            CycleOutcome::Executed => match (sound_was_on, self.is_sound_on()) {
+                (false, true) => CycleOutcome::SoundStarted,
+                (true, false) => CycleOutcome::SoundStopped,
+                _ => outcome,
+            },
+            outcome => outcome,
+        }
+    }
+
+    pub fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+    }
+
+    pub fn present(&mut self) -> bool {
+        let dirty_regions = self.gpu.take_dirty_regions();
+        if dirty_regions.is_empty() {
+            return false;
+        }
+
+        self.display.draw(self.gpu.get_memory(), &dirty_regions);
+
+        true
+    }
+
+    fn poll_keyboard(&mut self) {
+        self.keypad.update(self.keyboard.get_held_keys());
+    }
+
+    fn execute_instruction(&mut self) -> CycleOutcome {
+        self.poll_keyboard();
+
+        if let ChipsetState::WaitingForKey { x, pressed_key } = self.state {
+            return self.wait_for_key(x, pressed_key);
+        }
+
+        let mut skip_instruction = false;
+        let mut drew = false;
 
         let opcode = match self.current_opcode() {
-            Some(opcode) => {
-                match opcode.get_parts() {
-                    (0x0, 0x0, 0xe, 0x0) => {
-                        self.opcode_processor.clear_screen(&mut self.gpu);
-                        self.display.draw(self.gpu.get_memory());
-                    }
-                    (0x0, 0x0, 0xe, 0xe) => {
-                        self.opcode_processor
-                            .return_from_subroutine(&mut self.stack, &mut self.program_counter);
-                    }
-                    (0x1, _, _, _) => {
-                        self.opcode_processor
-                            .jump_to_address(&mut self.program_counter, opcode.get_address());
-                        skip_instruction = true;
-                    }
-                    (0x2, _, _, _) => {
-                        self.opcode_processor.call_subroutine(
-                            &mut self.program_counter,
-                            opcode.get_address(),
-                            &mut self.stack,
-                        );
-                        skip_instruction = true;
-                    }
-                    (0x3, _, _, _) => {
-                        self.opcode_processor.cond_vx_equal_nn(
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0x4, _, _, _) => {
-                        self.opcode_processor.cond_vx_not_equal_nn(
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0x5, _, _, 0x0) => {
-                        self.opcode_processor.cond_vx_equal_vy(
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0x6, _, _, _) => {
-                        self.opcode_processor.const_vx_equal_nn(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0x7, _, _, _) => {
-                        self.opcode_processor.const_vx_plus_equal_nn(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0x8, _, _, 0x0) => {
-                        self.opcode_processor.assign_vx_equal_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x1) => {
-                        self.opcode_processor.bitop_vx_equal_vx_or_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x2) => {
-                        self.opcode_processor.bitop_vx_equal_vx_and_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x3) => {
-                        self.opcode_processor.bitop_vx_equal_vx_xor_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x4) => {
-                        self.opcode_processor.math_vx_equal_vx_plus_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x5) => {
-                        self.opcode_processor.math_vx_equal_vx_minus_vy(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0x6) => {
-                        self.opcode_processor
-                            .bitop_vx_equal_vx_shr(&mut self.registers, opcode.get_x());
-                    }
-                    (0x8, _, _, 0x7) => {
-                        self.opcode_processor.math_vx_equal_vy_minus_vx(
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0x8, _, _, 0xe) => {
-                        self.opcode_processor
-                            .bitop_vx_equal_vx_shl(&mut self.registers, opcode.get_x());
-                    }
-                    (0x9, _, _, 0x0) => {
-                        self.opcode_processor.cond_vx_not_equal_vy(
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                            opcode.get_y(),
-                        );
-                    }
-                    (0xa, _, _, _) => {
-                        self.opcode_processor
-                            .mem_i_equal_nnn(&mut self.address_register, opcode.get_address());
-                    }
-                    (0xb, _, _, _) => {
-                        self.opcode_processor.flow_pc_equal_v0_plus_nnn(
-                            &mut self.program_counter,
-                            opcode.get_address(),
-                            &self.registers,
-                        );
-                        skip_instruction = true;
-                    }
-                    (0xc, _, _, _) => {
-                        self.opcode_processor.rand_vx_equal_rand_and_nn(
-                            &self.random_byte_generator,
-                            &mut self.registers,
-                            opcode.get_x(),
-                            opcode.get_short_address(),
-                        );
-                    }
-                    (0xd, _, _, _) => {
-                        self.opcode_processor.draw_vx_vy_n(
-                            opcode.get_x(),
-                            opcode.get_y(),
-                            opcode.get_n(),
-                            &mut self.gpu,
-                            &self.memory,
-                            self.address_register,
-                            &mut self.registers,
-                        );
-                        self.display.draw(self.gpu.get_memory());
-                    }
-                    (0xe, _, 0x9, 0xe) => {
-                        self.opcode_processor.keyop_if_key_equal_vx(
-                            &mut self.keyboard,
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xe, _, 0xa, 0x1) => {
-                        self.opcode_processor.keyop_if_key_not_equal_vx(
-                            &mut self.keyboard,
-                            &self.registers,
-                            &mut self.program_counter,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x0, 0x7) => {
-                        self.opcode_processor.timer_vx_equal_get_delay(
-                            self.delay_timer,
-                            &mut self.registers,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x0, 0xa) => {
-                        self.opcode_processor.keyop_vx_equal_key(
-                            &mut self.keyboard,
-                            &mut self.registers,
-                            opcode.get_x(),
-                            &mut self.program_counter,
-                        );
-                    }
-                    (0xf, _, 0x1, 0x5) => {
-                        self.opcode_processor.timer_delay_timer_equal_vx(
-                            &mut self.delay_timer,
-                            &self.registers,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x1, 0x8) => {
-                        self.opcode_processor.sound_sound_timer_equal_vx();
-                    }
-                    (0xf, _, 0x1, 0xe) => {
-                        self.opcode_processor.mem_i_equal_i_plus_vx(
-                            &mut self.registers,
-                            &mut self.address_register,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x2, 0x9) => {
-                        self.opcode_processor.mem_i_equal_sprite_addr_vx(
-                            &self.registers,
-                            &mut self.address_register,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x3, 0x3) => {
-                        self.opcode_processor.mem_bcd(
-                            &self.registers,
-                            self.address_register,
-                            &mut self.memory,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x5, 0x5) => {
-                        self.opcode_processor.mem_reg_dump(
-                            &self.registers,
-                            &mut self.memory,
-                            self.address_register,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0xf, _, 0x6, 0x5) => {
-                        self.opcode_processor.mem_reg_load(
-                            &mut self.registers,
-                            &self.memory,
-                            self.address_register,
-                            opcode.get_x(),
-                        );
-                    }
-                    (0x0, 0x0, 0x0, 0x0) => {
-                        return Err("No more opcodes".to_string());
-                    }
-                    _ => {
-                        panic!("Unknown opcode {:#x}", opcode);
-                    }
-                }
-                Ok(())
+            Some(opcode) => opcode,
+            None => {
+                return self.halt(HaltReason::ProgramCounterOutOfMemory(self.program_counter));
             }
-            None => Err("No more opcodes".to_string()),
         };
 
+        match opcode.get_parts() {
+            (0x0, 0x0, 0xe, 0x0) => {
+                self.opcode_processor.clear_screen(&mut self.gpu);
+                drew = true;
+            }
+            (0x0, 0x0, 0xe, 0xe) => {
+                self.opcode_processor
+                    .return_from_subroutine(&mut self.stack, &mut self.program_counter);
+            }
+            (0x1, _, _, _) => {
+                self.opcode_processor
+                    .jump_to_address(&mut self.program_counter, opcode.get_address());
+                skip_instruction = true;
+            }
+            (0x2, _, _, _) => {
+                self.opcode_processor.call_subroutine(
+                    &mut self.program_counter,
+                    opcode.get_address(),
+                    &mut self.stack,
+                );
+                skip_instruction = true;
+            }
+            (0x3, _, _, _) => {
+                self.opcode_processor.cond_vx_equal_nn(
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0x4, _, _, _) => {
+                self.opcode_processor.cond_vx_not_equal_nn(
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0x5, _, _, 0x0) => {
+                self.opcode_processor.cond_vx_equal_vy(
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0x6, _, _, _) => {
+                self.opcode_processor.const_vx_equal_nn(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0x7, _, _, _) => {
+                self.opcode_processor.const_vx_plus_equal_nn(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0x8, _, _, 0x0) => {
+                self.opcode_processor.assign_vx_equal_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x1) => {
+                self.opcode_processor.bitop_vx_equal_vx_or_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x2) => {
+                self.opcode_processor.bitop_vx_equal_vx_and_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x3) => {
+                self.opcode_processor.bitop_vx_equal_vx_xor_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x4) => {
+                self.opcode_processor.math_vx_equal_vx_plus_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x5) => {
+                self.opcode_processor.math_vx_equal_vx_minus_vy(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x6) => {
+                self.opcode_processor.bitop_vx_equal_vx_shr(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0x7) => {
+                self.opcode_processor.math_vx_equal_vy_minus_vx(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x8, _, _, 0xe) => {
+                self.opcode_processor.bitop_vx_equal_vx_shl(
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0x9, _, _, 0x0) => {
+                self.opcode_processor.cond_vx_not_equal_vy(
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                    opcode.get_y(),
+                );
+            }
+            (0xa, _, _, _) => {
+                self.opcode_processor
+                    .mem_i_equal_nnn(&mut self.address_register, opcode.get_address());
+            }
+            (0xb, _, _, _) => {
+                self.opcode_processor.flow_pc_equal_v0_plus_nnn(
+                    &mut self.program_counter,
+                    opcode.get_address(),
+                    &self.registers,
+                );
+                skip_instruction = true;
+            }
+            (0xc, _, _, _) => {
+                self.opcode_processor.rand_vx_equal_rand_and_nn(
+                    &self.random_byte_generator,
+                    &mut self.registers,
+                    opcode.get_x(),
+                    opcode.get_short_address(),
+                );
+            }
+            (0xd, _, _, _) => {
+                self.opcode_processor.draw_vx_vy_n(
+                    opcode.get_x(),
+                    opcode.get_y(),
+                    opcode.get_n(),
+                    &mut self.gpu,
+                    &self.memory,
+                    self.address_register,
+                    &mut self.registers,
+                );
+                drew = true;
+            }
+            (0xe, _, 0x9, 0xe) => {
+                self.opcode_processor.keyop_if_key_equal_vx(
+                    &self.keypad,
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                );
+            }
+            (0xe, _, 0xa, 0x1) => {
+                self.opcode_processor.keyop_if_key_not_equal_vx(
+                    &self.keypad,
+                    &self.registers,
+                    &mut self.program_counter,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x0, 0x7) => {
+                self.opcode_processor.timer_vx_equal_get_delay(
+                    self.delay_timer,
+                    &mut self.registers,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x0, 0xa) => {
+                self.opcode_processor
+                    .keyop_vx_equal_key(&mut self.state, opcode.get_x());
+            }
+            (0xf, _, 0x1, 0x5) => {
+                self.opcode_processor.timer_delay_timer_equal_vx(
+                    &mut self.delay_timer,
+                    &self.registers,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x1, 0x8) => {
+                self.opcode_processor.sound_sound_timer_equal_vx(
+                    &mut self.sound_timer,
+                    &self.registers,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x1, 0xe) => {
+                self.opcode_processor.mem_i_equal_i_plus_vx(
+                    &mut self.registers,
+                    &mut self.address_register,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x2, 0x9) => {
+                self.opcode_processor.mem_i_equal_sprite_addr_vx(
+                    &self.registers,
+                    &mut self.address_register,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x3, 0x3) => {
+                self.opcode_processor.mem_bcd(
+                    &self.registers,
+                    self.address_register,
+                    &mut self.memory,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x5, 0x5) => {
+                self.opcode_processor.mem_reg_dump(
+                    &self.registers,
+                    &mut self.memory,
+                    &mut self.address_register,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x6, 0x5) => {
+                self.opcode_processor.mem_reg_load(
+                    &mut self.registers,
+                    &self.memory,
+                    &mut self.address_register,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x7, 0x5) => {
+                self.opcode_processor.flags_dump(
+                    &self.registers,
+                    &mut self.persistent_flags,
+                    opcode.get_x(),
+                );
+            }
+            (0xf, _, 0x8, 0x5) => {
+                self.opcode_processor.flags_load(
+                    &mut self.registers,
+                    &self.persistent_flags,
+                    opcode.get_x(),
+                );
+            }
+            (0x0, 0x0, 0x0, 0x0) => {
+                return self.halt(HaltReason::EndOfProgram);
+            }
+            _ => {
+                return self.halt(HaltReason::UnknownOpCode(opcode.get_data()));
+            }
+        }
+
         if !skip_instruction {
             self.program_counter += INSTRUCTION_SIZE;
         }
 
-        opcode
+        if drew {
+            CycleOutcome::Drew
+        } else {
+            CycleOutcome::Executed
+        }
+    }
+
+    /// FX0A completes once a key has been pressed and released again; until then
+    /// the chipset keeps polling the keyboard without blocking.
+    fn wait_for_key(&mut self, x: u8, pressed_key: Option<Key>) -> CycleOutcome {
+        match pressed_key {
+            None => {
+                if let Some(key) = self.keypad.first_pressed_key() {
+                    self.state = ChipsetState::WaitingForKey {
+                        x,
+                        pressed_key: Some(key),
+                    };
+                }
+                CycleOutcome::WaitingForKey
+            }
+            Some(key) if !self.keypad.is_held(key) => {
+                self.registers.set_register_at(x as usize, key as u8);
+                self.state = ChipsetState::Running;
+                CycleOutcome::Executed
+            }
+            Some(_) => CycleOutcome::WaitingForKey,
+        }
+    }
+
+    pub fn halt(&mut self, halt_reason: HaltReason) -> CycleOutcome {
+        self.halt_reason = Some(halt_reason);
+
+        CycleOutcome::Halted(halt_reason)
     }
 
     fn current_opcode(&mut self) -> Option<OpCode> {
@@ -347,17 +801,18 @@ impl<O: OpCodesProcessor, G: Gpu, K: Keyboard, D: GraphicDisplay, R: RandomByteG
 #[cfg(test)]
 mod test_chipset {
     use super::*;
-    use crate::display::GraphicDisplay;
+    use crate::display::{DirtyRegion, GraphicDisplay};
     use crate::gpu::Chip8Gpu;
     use crate::keyboard::{Key, Keyboard};
     use crate::memory::{Memory, Registers, Stack};
+    use crate::opcode_processor::Chip8OpCodesProcessor;
     use rand;
     use std::cell::Cell;
     use std::ops;
 
     struct MockedGraphicDisplay {}
     impl GraphicDisplay for MockedGraphicDisplay {
-        fn draw<M>(&mut self, _: &M)
+        fn draw<M>(&mut self, _: &M, _: &[DirtyRegion])
         where
             M: ops::Index<usize, Output = [u8]>,
         {
@@ -372,13 +827,19 @@ mod test_chipset {
         }
     }
 
-    struct MockedKeyboard {}
+    struct MockedKeyboard {
+        held_keys: Vec<u16>,
+    }
     impl Keyboard for MockedKeyboard {
-        fn wait_for_key_press(&mut self) -> Key {
-            Key::Key0
+        fn get_held_keys(&mut self) -> u16 {
+            if self.held_keys.is_empty() {
+                return 0;
+            }
+
+            self.held_keys.remove(0)
         }
 
-        fn get_pressed_key(&mut self) -> Option<Key> {
+        fn poll_host_event(&mut self) -> Option<HostEvent> {
             None
         }
     }
@@ -403,7 +864,9 @@ mod test_chipset {
             registers,
             MockedOpCodesProcessor::new(),
             Chip8Gpu::new(),
-            MockedKeyboard {},
+            MockedKeyboard {
+                held_keys: Vec::new(),
+            },
             MockedGraphicDisplay {},
             TestRandomByteGenerator {},
         );
@@ -413,7 +876,7 @@ mod test_chipset {
     }
 
     fn get_opcodes() -> Vec<(&'static str, u16)> {
-        let mut opcodes = Vec::with_capacity(34);
+        let mut opcodes = Vec::with_capacity(36);
 
         opcodes.push(("clear_screen", 0x00e0));
         opcodes.push(("return_from_subroutine", 0x00ee));
@@ -449,6 +912,8 @@ mod test_chipset {
         opcodes.push(("mem_bcd", 0xf533));
         opcodes.push(("mem_reg_dump", 0xf555));
         opcodes.push(("mem_reg_load", 0xf565));
+        opcodes.push(("flags_dump", 0xf575));
+        opcodes.push(("flags_load", 0xf585));
 
         opcodes
     }
@@ -468,7 +933,9 @@ mod test_chipset {
                 registers,
                 MockedOpCodesProcessor::new(),
                 Chip8Gpu::new(),
-                MockedKeyboard {},
+                MockedKeyboard {
+                    held_keys: Vec::new(),
+                },
                 MockedGraphicDisplay {},
                 TestRandomByteGenerator {},
             );
@@ -481,6 +948,171 @@ mod test_chipset {
         }
     }
 
+    fn create_chipset(
+        program_data: &[u8],
+    ) -> Chip8Chipset<
+        Chip8OpCodesProcessor,
+        Chip8Gpu,
+        MockedKeyboard,
+        MockedGraphicDisplay,
+        TestRandomByteGenerator,
+    > {
+        let (mut memory, stack, registers) = create_memory();
+        load_data_into_memory(&mut memory, program_data);
+
+        Chip8Chipset::new(
+            memory,
+            stack,
+            registers,
+            Chip8OpCodesProcessor::new(),
+            Chip8Gpu::new(),
+            MockedKeyboard {
+                held_keys: Vec::new(),
+            },
+            MockedGraphicDisplay {},
+            TestRandomByteGenerator {},
+        )
+    }
+
+    #[test]
+    fn test_tick_reports_executed_and_drew() {
+        let mut chipset = create_chipset(&[0x60, 0x01, 0xd0, 0x05]);
+
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::Drew, chipset.tick());
+    }
+
+    #[test]
+    fn test_tick_reports_sound_started_and_stopped() {
+        let mut chipset = create_chipset(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]);
+
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::SoundStarted, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::SoundStopped, chipset.tick());
+    }
+
+    #[test]
+    fn test_tick_reports_drew_when_sound_stops_too() {
+        let mut chipset = create_chipset(&[0x60, 0x01, 0xf0, 0x18, 0xd0, 0x05]);
+
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::SoundStarted, chipset.tick());
+        assert!(chipset.is_sound_on());
+
+        assert_eq!(CycleOutcome::Drew, chipset.tick());
+        assert!(!chipset.is_sound_on());
+    }
+
+    #[test]
+    fn test_halt_is_permanent() {
+        let mut chipset = create_chipset(&[0x00, 0xe0]);
+
+        assert_eq!(CycleOutcome::Drew, chipset.tick());
+        assert_eq!(
+            CycleOutcome::Halted(HaltReason::EndOfProgram),
+            chipset.tick()
+        );
+        assert_eq!(
+            CycleOutcome::Halted(HaltReason::EndOfProgram),
+            chipset.step()
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode_halts() {
+        let mut chipset = create_chipset(&[0x5a, 0xb1]);
+
+        assert_eq!(
+            CycleOutcome::Halted(HaltReason::UnknownOpCode(0x5ab1)),
+            chipset.tick()
+        );
+    }
+
+    #[test]
+    fn test_breakpoint_is_hit_once_before_instruction_runs() {
+        let mut chipset = create_chipset(&[0x60, 0x01, 0x60, 0x02, 0x12, 0x00]);
+
+        assert!(chipset.toggle_breakpoint(0x202));
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::BreakpointHit, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::BreakpointHit, chipset.tick());
+
+        assert!(!chipset.toggle_breakpoint(0x202));
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert!(chipset.get_breakpoints().is_empty());
+    }
+
+    #[test]
+    fn test_key_wait_resumes_on_release_and_keeps_timers_running() {
+        let mut chipset = create_chipset(&[0x60, 0x05, 0xf0, 0x15, 0xf1, 0x0a, 0x12, 0x06]);
+        let (key_5, key_a) = (Key::Key5.mask(), Key::KeyA.mask());
+        chipset.keyboard.held_keys = vec![0, 0, key_5, key_5, key_a | key_5, key_a, 0];
+
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert!(chipset.is_waiting_for_key());
+
+        assert_eq!(CycleOutcome::WaitingForKey, chipset.tick());
+        assert_eq!(CycleOutcome::WaitingForKey, chipset.tick());
+        assert_eq!(CycleOutcome::WaitingForKey, chipset.tick());
+        assert_eq!(0x0, chipset.registers.get_register_at(0x1));
+
+        assert_eq!(CycleOutcome::Executed, chipset.tick());
+        assert!(!chipset.is_waiting_for_key());
+        assert_eq!(0xa, chipset.registers.get_register_at(0x1));
+        assert_eq!(0x206, chipset.program_counter);
+        assert_eq!(0x0, chipset.delay_timer);
+    }
+
+    #[test]
+    fn test_persistent_flags_outlive_reset() {
+        let mut chipset = create_chipset(&[0x60, 0x2a, 0xf0, 0x75]);
+        chipset.tick();
+        chipset.tick();
+        assert_eq!(0x2a, chipset.get_persistent_flags()[0]);
+
+        let (mut memory, _, _) = create_memory();
+        load_data_into_memory(&mut memory, &[0xf0, 0x85]);
+        chipset.reset(memory);
+        chipset.tick();
+
+        assert_eq!(0x2a, chipset.get_debug_state().registers[0]);
+    }
+
+    #[test]
+    fn test_machine_state_survives_byte_round_trip() {
+        // Calls a subroutine that draws a sprite and waits for a key.
+        let mut chipset = create_chipset(&[0x22, 0x04, 0x00, 0x00, 0xd0, 0x05, 0xf3, 0x0a]);
+        chipset.tick();
+        chipset.tick();
+        chipset.tick();
+        assert!(chipset.is_waiting_for_key());
+
+        let state = chipset.save_state();
+        let bytes = state.to_bytes();
+
+        assert!(state == MachineState::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_machine_state_rejects_invalid_bytes() {
+        let bytes = create_chipset(&[0x12, 0x00]).save_state().to_bytes();
+
+        assert_eq!(
+            Err(StateError::InvalidHeader),
+            MachineState::from_bytes(&bytes[1..]).map(|_| ())
+        );
+        assert_eq!(
+            Err(StateError::InvalidLength(bytes.len() - 1)),
+            MachineState::from_bytes(&bytes[..bytes.len() - 1]).map(|_| ())
+        );
+    }
+
     fn create_memory() -> (Memory, Stack, Registers) {
         (Memory::new(), Stack::new(), Registers::new())
     }
@@ -578,13 +1210,13 @@ mod test_chipset {
         fn math_vx_equal_vx_minus_vy(&self, _registers: &mut Registers, _x: u8, _y: u8) {
             self.set_matched_method("math_vx_equal_vx_minus_vy");
         }
-        fn bitop_vx_equal_vx_shr(&self, _registers: &mut Registers, _x: u8) {
+        fn bitop_vx_equal_vx_shr(&self, _registers: &mut Registers, _x: u8, _y: u8) {
             self.set_matched_method("bitop_vx_equal_vx_shr");
         }
         fn math_vx_equal_vy_minus_vx(&self, _registers: &mut Registers, _x: u8, _y: u8) {
             self.set_matched_method("math_vx_equal_vy_minus_vx");
         }
-        fn bitop_vx_equal_vx_shl(&self, _registers: &mut Registers, _x: u8) {
+        fn bitop_vx_equal_vx_shl(&self, _registers: &mut Registers, _x: u8, _y: u8) {
             self.set_matched_method("bitop_vx_equal_vx_shl");
         }
         fn cond_vx_not_equal_vy(
@@ -659,7 +1291,7 @@ mod test_chipset {
             &self,
             _registers: &Registers,
             _memory: &mut Memory,
-            _address_register: u16,
+            _address_register: &mut u16,
             _x: u8,
         ) {
             self.set_matched_method("mem_reg_dump");
@@ -668,42 +1300,36 @@ mod test_chipset {
             &self,
             _registers: &mut Registers,
             _memory: &Memory,
-            _address_register: u16,
+            _address_register: &mut u16,
             _x: u8,
         ) {
             self.set_matched_method("mem_reg_load");
         }
-        fn keyop_if_key_equal_vx<K>(
+        fn flags_dump(&self, _registers: &Registers, _flags: &mut PersistentFlags, _x: u8) {
+            self.set_matched_method("flags_dump");
+        }
+        fn flags_load(&self, _registers: &mut Registers, _flags: &PersistentFlags, _x: u8) {
+            self.set_matched_method("flags_load");
+        }
+        fn keyop_if_key_equal_vx(
             &self,
-            _keyboard: &mut K,
+            _keypad: &Keypad,
             _registers: &Registers,
             _program_counter: &mut u16,
             _x: u8,
-        ) where
-            K: Keyboard,
-        {
+        ) {
             self.set_matched_method("keyop_if_key_equal_vx");
         }
-        fn keyop_if_key_not_equal_vx<K>(
+        fn keyop_if_key_not_equal_vx(
             &self,
-            _keyboard: &mut K,
+            _keypad: &Keypad,
             _registers: &Registers,
             _program_counter: &mut u16,
             _x: u8,
-        ) where
-            K: Keyboard,
-        {
+        ) {
             self.set_matched_method("keyop_if_key_not_equal_vx");
         }
-        fn keyop_vx_equal_key<K>(
-            &self,
-            _keyboard: &mut K,
-            _registers: &mut Registers,
-            _x: u8,
-            _program_counter: &mut u16,
-        ) where
-            K: Keyboard,
-        {
+        fn keyop_vx_equal_key(&self, _state: &mut ChipsetState, _x: u8) {
             self.set_matched_method("keyop_vx_equal_key");
         }
         fn timer_vx_equal_get_delay(&self, _delay_timer: u8, _registers: &mut Registers, _x: u8) {
@@ -717,7 +1343,12 @@ mod test_chipset {
         ) {
             self.set_matched_method("timer_delay_timer_equal_vx");
         }
-        fn sound_sound_timer_equal_vx(&self) {
+        fn sound_sound_timer_equal_vx(
+            &self,
+            _sound_timer: &mut u8,
+            _registers: &Registers,
+            _x: u8,
+        ) {
             self.set_matched_method("sound_sound_timer_equal_vx");
         }
     }
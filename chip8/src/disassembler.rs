@@ -0,0 +1,76 @@
+use crate::opcode_processor::OpCode;
+
+/// Mnemonic for an instruction, in the syntax of Cowgod's CHIP-8 reference.
+/// Data that does not decode to an instruction is shown as `DW`.
+pub fn disassemble(data: u16) -> String {
+    let opcode = OpCode::from_data(data);
+    let (x, y, nn, nnn) = (
+        opcode.get_x(),
+        opcode.get_y(),
+        opcode.get_short_address(),
+        opcode.get_address(),
+    );
+
+    match opcode.get_parts() {
+        (0x0, 0x0, 0xe, 0x0) => "CLS".to_owned(),
+        (0x0, 0x0, 0xe, 0xe) => "RET".to_owned(),
+        (0x0, _, _, _) if data != 0 => format!("SYS {:#05x}", nnn),
+        (0x1, _, _, _) => format!("JP {:#05x}", nnn),
+        (0x2, _, _, _) => format!("CALL {:#05x}", nnn),
+        (0x3, _, _, _) => format!("SE V{:X}, {:#04x}", x, nn),
+        (0x4, _, _, _) => format!("SNE V{:X}, {:#04x}", x, nn),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, _, _, _) => format!("LD V{:X}, {:#04x}", x, nn),
+        (0x7, _, _, _) => format!("ADD V{:X}, {:#04x}", x, nn),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xe) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xa, _, _, _) => format!("LD I, {:#05x}", nnn),
+        (0xb, _, _, _) => format!("JP V0, {:#05x}", nnn),
+        (0xc, _, _, _) => format!("RND V{:X}, {:#04x}", x, nn),
+        (0xd, _, _, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xe, _, 0x9, 0xe) => format!("SKP V{:X}", x),
+        (0xe, _, 0xa, 0x1) => format!("SKNP V{:X}", x),
+        (0xf, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xf, _, 0x0, 0xa) => format!("LD V{:X}, K", x),
+        (0xf, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xf, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xf, _, 0x1, 0xe) => format!("ADD I, V{:X}", x),
+        (0xf, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xf, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xf, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xf, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        (0xf, _, 0x7, 0x5) => format!("LD R, V{:X}", x),
+        (0xf, _, 0x8, 0x5) => format!("LD V{:X}, R", x),
+        _ => format!("DW {:#06x}", data),
+    }
+}
+
+#[cfg(test)]
+mod test_disassembler {
+    use super::disassemble;
+
+    #[test]
+    fn test_disassembles_instructions() {
+        assert_eq!("CLS", disassemble(0x00e0));
+        assert_eq!("JP 0x228", disassemble(0x1228));
+        assert_eq!("SE V3, 0x0a", disassemble(0x330a));
+        assert_eq!("SHR VA, VB", disassemble(0x8ab6));
+        assert_eq!("DRW V0, V1, 5", disassemble(0xd015));
+        assert_eq!("LD VF, [I]", disassemble(0xff65));
+    }
+
+    #[test]
+    fn test_unknown_data_is_shown_as_word() {
+        assert_eq!("DW 0x0000", disassemble(0x0000));
+        assert_eq!("DW 0x5121", disassemble(0x5121));
+        assert_eq!("DW 0xe0ff", disassemble(0xe0ff));
+    }
+}
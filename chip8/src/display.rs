@@ -1,10 +1,54 @@
+use std::cmp;
 use std::ops;
 
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DirtyRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRegion {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        DirtyRegion {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn full_screen() -> Self {
+        DirtyRegion::new(0, 0, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+
+    pub fn overlaps(&self, other: &DirtyRegion) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    pub fn union(&self, other: &DirtyRegion) -> DirtyRegion {
+        let x = cmp::min(self.x, other.x);
+        let y = cmp::min(self.y, other.y);
+        let right = cmp::max(self.x + self.width, other.x + other.width);
+        let bottom = cmp::max(self.y + self.height, other.y + other.height);
+
+        DirtyRegion::new(x, y, right - x, bottom - y)
+    }
+}
+
 pub trait GraphicDisplay {
-    fn draw<M>(&mut self, memory: &M)
+    fn draw<M>(&mut self, memory: &M, dirty_regions: &[DirtyRegion])
     where
         M: ops::Index<usize, Output = [u8]>;
+
+    /// Forgets whatever the display remembers about the last frame, so that
+    /// the next draw repaints from scratch.
+    fn invalidate(&mut self) {}
 }
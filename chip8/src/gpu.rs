@@ -1,11 +1,13 @@
-use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::display::{DirtyRegion, DISPLAY_HEIGHT, DISPLAY_WIDTH};
 use crate::memory::Memory;
+use std::cmp;
 use std::ops;
 
 const DISPLAY_MAX_X: u8 = DISPLAY_WIDTH as u8 - 1;
 const DISPLAY_MAX_Y: u8 = DISPLAY_HEIGHT as u8 - 1;
 const SPRITE_WIDTH: u8 = 8;
 
+#[derive(Clone, PartialEq)]
 pub struct GraphicMemory {
     memory: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
 }
@@ -42,14 +44,32 @@ impl ops::IndexMut<usize> for GraphicMemory {
 
 pub struct Chip8Gpu {
     memory: GraphicMemory,
+    dirty_regions: Vec<DirtyRegion>,
 }
 
 impl Chip8Gpu {
     pub fn new() -> Self {
         Chip8Gpu {
             memory: GraphicMemory::new(),
+            dirty_regions: Vec::new(),
         }
     }
+
+    fn mark_dirty(&mut self, region: DirtyRegion) {
+        let mut merged = region;
+        let mut index = 0;
+
+        while index < self.dirty_regions.len() {
+            if self.dirty_regions[index].overlaps(&merged) {
+                merged = merged.union(&self.dirty_regions.remove(index));
+                index = 0;
+            } else {
+                index += 1;
+            }
+        }
+
+        self.dirty_regions.push(merged);
+    }
 }
 
 pub trait Gpu {
@@ -63,11 +83,16 @@ pub trait Gpu {
     ) -> bool;
     fn clear(&mut self);
     fn get_memory(&self) -> &GraphicMemory;
+    fn take_dirty_regions(&mut self) -> Vec<DirtyRegion>;
+    fn load_memory(&mut self, memory: &GraphicMemory);
+    /// Marks the whole screen dirty so that it is presented again.
+    fn invalidate(&mut self);
 }
 
 impl Gpu for Chip8Gpu {
     fn clear(&mut self) {
         self.memory.clear();
+        self.dirty_regions = vec![DirtyRegion::full_screen()];
     }
 
     fn draw_sprite(
@@ -79,6 +104,7 @@ impl Gpu for Chip8Gpu {
         memory: &Memory,
     ) -> bool {
         let mut is_flipped = false;
+        let mut changed: Option<(u8, u8, u8, u8)> = None;
 
         let mut display_y = if start_y > DISPLAY_MAX_Y as u8 {
             start_y % (DISPLAY_HEIGHT as u8)
@@ -115,23 +141,57 @@ impl Gpu for Chip8Gpu {
                 if old_pixel & new_pixel == 1 {
                     is_flipped = true;
                 }
+                if new_pixel == 1 {
+                    changed = Some(match changed {
+                        Some((min_x, min_y, max_x, max_y)) => (
+                            cmp::min(min_x, display_x),
+                            cmp::min(min_y, display_y),
+                            cmp::max(max_x, display_x),
+                            cmp::max(max_y, display_y),
+                        ),
+                        None => (display_x, display_y, display_x, display_y),
+                    });
+                }
 
                 display_x += 1;
             }
             display_y += 1;
         }
 
+        if let Some((min_x, min_y, max_x, max_y)) = changed {
+            self.mark_dirty(DirtyRegion::new(
+                min_x as usize,
+                min_y as usize,
+                (max_x - min_x) as usize + 1,
+                (max_y - min_y) as usize + 1,
+            ));
+        }
+
         is_flipped
     }
 
     fn get_memory(&self) -> &GraphicMemory {
         &self.memory
     }
+
+    fn take_dirty_regions(&mut self) -> Vec<DirtyRegion> {
+        self.dirty_regions.drain(..).collect()
+    }
+
+    fn load_memory(&mut self, memory: &GraphicMemory) {
+        self.memory = memory.clone();
+        self.dirty_regions = vec![DirtyRegion::full_screen()];
+    }
+
+    fn invalidate(&mut self) {
+        self.dirty_regions = vec![DirtyRegion::full_screen()];
+    }
 }
 
 #[cfg(test)]
 mod test_display {
     use super::{Chip8Gpu, Gpu};
+    use crate::display::DirtyRegion;
     use crate::memory::Memory;
 
     impl Chip8Gpu {
@@ -186,4 +246,66 @@ mod test_display {
             }
         }
     }
+
+    #[test]
+    fn test_draw_sprite_marks_changed_pixels_as_dirty() {
+        let mut memory = Memory::new();
+        let address_register = 0x100;
+        memory.write(address_register, 0b0011_0000);
+        memory.write(address_register + 1, 0b0001_0000);
+
+        let mut gpu = Chip8Gpu::new();
+        gpu.draw_sprite(10, 5, 2, address_register, &memory);
+
+        assert_eq!(
+            vec![DirtyRegion::new(12, 5, 2, 2)],
+            gpu.take_dirty_regions()
+        );
+        assert!(gpu.take_dirty_regions().is_empty());
+    }
+
+    #[test]
+    fn test_draw_empty_sprite_does_not_mark_anything_as_dirty() {
+        let memory = Memory::new();
+
+        let mut gpu = Chip8Gpu::new();
+        gpu.draw_sprite(10, 5, 5, 0x100, &memory);
+
+        assert!(gpu.take_dirty_regions().is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_dirty_regions_are_merged() {
+        let mut memory = Memory::new();
+        let address_register = 0x100;
+        for address in address_register..0x104 {
+            memory.write(address, 0xff);
+        }
+
+        let mut gpu = Chip8Gpu::new();
+        gpu.draw_sprite(0, 0, 4, address_register, &memory);
+        gpu.draw_sprite(4, 2, 4, address_register, &memory);
+        gpu.draw_sprite(40, 20, 1, address_register, &memory);
+
+        assert_eq!(
+            vec![
+                DirtyRegion::new(0, 0, 12, 6),
+                DirtyRegion::new(40, 20, 8, 1)
+            ],
+            gpu.take_dirty_regions()
+        );
+    }
+
+    #[test]
+    fn test_clear_marks_whole_screen_as_dirty() {
+        let mut memory = Memory::new();
+        let address_register = 0x100;
+        memory.write(address_register, 0xff);
+
+        let mut gpu = Chip8Gpu::new();
+        gpu.draw_sprite(0, 0, 1, address_register, &memory);
+        gpu.clear();
+
+        assert_eq!(vec![DirtyRegion::full_screen()], gpu.take_dirty_regions());
+    }
 }
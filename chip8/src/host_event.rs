@@ -0,0 +1,18 @@
+/// Requests coming from the machine running the emulator rather than from the
+/// CHIP-8 keypad. They are handled by `InitializedEmulator` between instructions,
+/// whatever the ROM happens to be doing.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HostEvent {
+    Quit,
+    TogglePause,
+    Reset,
+    SaveState,
+    LoadState,
+    SpeedUp,
+    SlowDown,
+    Redraw,
+    /// Executes a single instruction while paused.
+    Step,
+    /// Toggles a breakpoint at the current program counter.
+    ToggleBreakpoint,
+}
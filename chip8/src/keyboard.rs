@@ -1,6 +1,11 @@
+use crate::host_event::HostEvent;
+
+pub const KEYS_COUNT: usize = 0x10;
+
 pub trait Keyboard {
-    fn wait_for_key_press(&mut self) -> Key;
-    fn get_pressed_key(&mut self) -> Option<Key>;
+    /// Returns the keys currently held down, bit `n` standing for key `n`.
+    fn get_held_keys(&mut self) -> u16;
+    fn poll_host_event(&mut self) -> Option<HostEvent>;
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -21,5 +26,118 @@ pub enum Key {
     KeyD = 0xd,
     KeyE = 0xe,
     KeyF = 0xf,
-    KeyESC = 0xff,
+}
+
+impl Key {
+    pub fn from_index(index: u8) -> Option<Key> {
+        match index {
+            0x0 => Some(Key::Key0),
+            0x1 => Some(Key::Key1),
+            0x2 => Some(Key::Key2),
+            0x3 => Some(Key::Key3),
+            0x4 => Some(Key::Key4),
+            0x5 => Some(Key::Key5),
+            0x6 => Some(Key::Key6),
+            0x7 => Some(Key::Key7),
+            0x8 => Some(Key::Key8),
+            0x9 => Some(Key::Key9),
+            0xa => Some(Key::KeyA),
+            0xb => Some(Key::KeyB),
+            0xc => Some(Key::KeyC),
+            0xd => Some(Key::KeyD),
+            0xe => Some(Key::KeyE),
+            0xf => Some(Key::KeyF),
+            _ => None,
+        }
+    }
+
+    pub fn mask(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// Keypad state as seen by the chipset, remembering the previous poll so that
+/// press and release edges can be told apart from keys that are merely held.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Keypad {
+    held_keys: u16,
+    previously_held_keys: u16,
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Keypad::default()
+    }
+
+    pub fn update(&mut self, held_keys: u16) {
+        self.previously_held_keys = self.held_keys;
+        self.held_keys = held_keys;
+    }
+
+    pub fn get_held_keys(&self) -> u16 {
+        self.held_keys
+    }
+
+    pub fn is_held(&self, key: Key) -> bool {
+        self.held_keys & key.mask() != 0
+    }
+
+    pub fn get_pressed_keys(&self) -> u16 {
+        self.held_keys & !self.previously_held_keys
+    }
+
+    pub fn get_released_keys(&self) -> u16 {
+        self.previously_held_keys & !self.held_keys
+    }
+
+    pub fn first_pressed_key(&self) -> Option<Key> {
+        Self::first_key(self.get_pressed_keys())
+    }
+
+    fn first_key(keys: u16) -> Option<Key> {
+        if keys == 0 {
+            return None;
+        }
+
+        Key::from_index(keys.trailing_zeros() as u8)
+    }
+}
+
+#[cfg(test)]
+mod test_keypad {
+    use super::{Key, Keypad};
+
+    #[test]
+    fn test_key_mask() {
+        assert_eq!(0b0000_0000_0000_0001, Key::Key0.mask());
+        assert_eq!(0b1000_0000_0000_0000, Key::KeyF.mask());
+    }
+
+    #[test]
+    fn test_multiple_keys_can_be_held() {
+        let mut keypad = Keypad::new();
+        keypad.update(Key::Key4.mask() | Key::Key6.mask());
+
+        assert!(keypad.is_held(Key::Key4));
+        assert!(keypad.is_held(Key::Key6));
+        assert!(!keypad.is_held(Key::Key5));
+    }
+
+    #[test]
+    fn test_press_and_release_edges() {
+        let mut keypad = Keypad::new();
+
+        keypad.update(Key::Key4.mask());
+        assert_eq!(Key::Key4.mask(), keypad.get_pressed_keys());
+        assert_eq!(Some(Key::Key4), keypad.first_pressed_key());
+
+        keypad.update(Key::Key4.mask() | Key::KeyA.mask());
+        assert_eq!(Key::KeyA.mask(), keypad.get_pressed_keys());
+        assert_eq!(0, keypad.get_released_keys());
+
+        keypad.update(Key::KeyA.mask());
+        assert_eq!(0, keypad.get_pressed_keys());
+        assert_eq!(Key::Key4.mask(), keypad.get_released_keys());
+        assert_eq!(None, keypad.first_pressed_key());
+    }
 }
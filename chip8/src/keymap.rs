@@ -0,0 +1,343 @@
+use crate::host_event::HostEvent;
+use crate::keyboard::Key;
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const ESCAPE: char = '\u{1b}';
+/// What terminals send for Ctrl+L, the usual redraw key.
+pub const CONTROL_L: char = '\u{c}';
+pub const PRESETS: [&str; 4] = ["qwerty", "azerty", "qwertz", "dvorak"];
+
+const KEYPAD_ORDER: [Key; 16] = [
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::KeyC,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::KeyD,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::KeyE,
+    Key::KeyA,
+    Key::Key0,
+    Key::KeyB,
+    Key::KeyF,
+];
+
+const HOST_ORDER: [HostEvent; 8] = [
+    HostEvent::Quit,
+    HostEvent::TogglePause,
+    HostEvent::Reset,
+    HostEvent::SaveState,
+    HostEvent::LoadState,
+    HostEvent::SpeedUp,
+    HostEvent::SlowDown,
+    HostEvent::Redraw,
+];
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Binding {
+    Keypad(Key),
+    Host(HostEvent),
+}
+
+/// Maps characters typed on the host keyboard to keypad keys or host events.
+/// Letters are matched case-insensitively.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Keymap {
+    bindings: BTreeMap<char, Binding>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Keymap::default()
+    }
+
+    /// The keypad always sits on the four leftmost columns of the keyboard,
+    /// laid out as 123C/456D/789E/A0BF.
+    pub fn qwerty() -> Self {
+        Keymap::from_rows("1234qwerasdfzxcv", "\u{1b}pmkl+-\u{c}")
+    }
+
+    pub fn azerty() -> Self {
+        let mut keymap = Keymap::from_rows("1234azerqsdfwxcv", "\u{1b}pmkl+-\u{c}");
+        // The digit row needs shift on AZERTY, so accept the unshifted symbols too.
+        for (character, key) in "&é\"'".chars().zip(KEYPAD_ORDER.iter()) {
+            keymap.bind(character, Binding::Keypad(*key));
+        }
+
+        keymap
+    }
+
+    pub fn qwertz() -> Self {
+        Keymap::from_rows("1234qwerasdfyxcv", "\u{1b}pmkl+-\u{c}")
+    }
+
+    /// Dvorak puts P and K under the keypad, so host controls keep their
+    /// QWERTY positions instead of their letters.
+    pub fn dvorak() -> Self {
+        Keymap::from_rows("1234',.paoeu;qjk", "\u{1b}lmtn+-\u{c}")
+    }
+
+    pub fn from_preset(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "qwerty" => Some(Keymap::qwerty()),
+            "azerty" => Some(Keymap::azerty()),
+            "qwertz" => Some(Keymap::qwertz()),
+            "dvorak" => Some(Keymap::dvorak()),
+            _ => None,
+        }
+    }
+
+    fn from_rows(keypad: &str, host: &str) -> Self {
+        let mut keymap = Keymap::new();
+        for (character, key) in keypad.chars().zip(KEYPAD_ORDER.iter()) {
+            keymap.bind(character, Binding::Keypad(*key));
+        }
+        for (character, event) in host.chars().zip(HOST_ORDER.iter()) {
+            keymap.bind(character, Binding::Host(*event));
+        }
+
+        keymap
+    }
+
+    pub fn bind(&mut self, character: char, binding: Binding) {
+        self.bindings.insert(
+            character.to_lowercase().next().unwrap_or(character),
+            binding,
+        );
+    }
+
+    pub fn unbind(&mut self, character: char) {
+        self.bindings
+            .remove(&character.to_lowercase().next().unwrap_or(character));
+    }
+
+    pub fn get(&self, character: char) -> Option<Binding> {
+        self.bindings
+            .get(&character.to_lowercase().next().unwrap_or(character))
+            .cloned()
+    }
+}
+
+/// Parses a key name as written in a config file or reported by a browser
+/// `KeyboardEvent.key`: either a single character or one of a few names.
+pub fn parse_key_name(name: &str) -> Option<char> {
+    let mut characters = name.chars();
+    if let (Some(character), None) = (characters.next(), characters.next()) {
+        return Some(character);
+    }
+
+    match name.to_ascii_lowercase().as_str() {
+        "esc" | "escape" => Some(ESCAPE),
+        "ctrl-l" => Some(CONTROL_L),
+        "space" => Some(' '),
+        "plus" => Some('+'),
+        "minus" => Some('-'),
+        "equals" => Some('='),
+        _ => None,
+    }
+}
+
+fn parse_binding(value: &str) -> Option<Binding> {
+    match value.to_ascii_lowercase().as_str() {
+        "quit" => Some(Binding::Host(HostEvent::Quit)),
+        "pause" => Some(Binding::Host(HostEvent::TogglePause)),
+        "reset" => Some(Binding::Host(HostEvent::Reset)),
+        "save" => Some(Binding::Host(HostEvent::SaveState)),
+        "load" => Some(Binding::Host(HostEvent::LoadState)),
+        "faster" => Some(Binding::Host(HostEvent::SpeedUp)),
+        "slower" => Some(Binding::Host(HostEvent::SlowDown)),
+        "redraw" => Some(Binding::Host(HostEvent::Redraw)),
+        value => u8::from_str_radix(value, 16)
+            .ok()
+            .and_then(Key::from_index)
+            .map(Binding::Keypad),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Keymap settings read from a config file:
+///
+/// ```text
+/// layout = azerty
+/// esc = quit
+///
+/// [PONG]
+/// k = 1
+/// m = 4
+/// ```
+///
+/// Keys before the first section apply to every ROM. A section named after
+/// a ROM file starts from those and can switch `layout` or rebind keys.
+/// Values are a keypad key in hex or one of quit, pause, reset, save, load,
+/// faster, slower and redraw; `none` removes a binding.
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeymapConfig {
+    default: Keymap,
+    rom_overrides: BTreeMap<String, Keymap>,
+}
+
+impl KeymapConfig {
+    pub fn new(default: Keymap) -> Self {
+        KeymapConfig {
+            default,
+            rom_overrides: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, KeymapError> {
+        let mut config = KeymapConfig::new(Keymap::qwerty());
+        let mut section: Option<String> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| KeymapError {
+                line: line_number,
+                message,
+            };
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_owned();
+                if name.is_empty() {
+                    return Err(error("empty section name".to_owned()));
+                }
+                let keymap = config.default.clone();
+                config.rom_overrides.entry(name.clone()).or_insert(keymap);
+                section = Some(name);
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => return Err(error(format!("expected `key = value`, got `{}`", line))),
+            };
+
+            let keymap = match &section {
+                Some(section) => config.rom_overrides.get_mut(section).unwrap(),
+                None => &mut config.default,
+            };
+
+            if name == "layout" {
+                *keymap = Keymap::from_preset(value)
+                    .ok_or_else(|| error(format!("unknown layout `{}`", value)))?;
+                continue;
+            }
+
+            let character =
+                parse_key_name(name).ok_or_else(|| error(format!("unknown key `{}`", name)))?;
+            if value.eq_ignore_ascii_case("none") {
+                keymap.unbind(character);
+                continue;
+            }
+            let binding = parse_binding(value)
+                .ok_or_else(|| error(format!("unknown binding `{}`", value)))?;
+            keymap.bind(character, binding);
+        }
+
+        Ok(config)
+    }
+
+    pub fn get_default(&self) -> &Keymap {
+        &self.default
+    }
+
+    pub fn keymap_for(&self, rom_name: &str) -> &Keymap {
+        self.rom_overrides.get(rom_name).unwrap_or(&self.default)
+    }
+}
+
+impl Default for KeymapConfig {
+    fn default() -> Self {
+        KeymapConfig::new(Keymap::qwerty())
+    }
+}
+
+#[cfg(test)]
+mod test_keymap {
+    use super::{parse_key_name, Binding, Keymap, KeymapConfig, ESCAPE};
+    use crate::host_event::HostEvent;
+    use crate::keyboard::Key;
+
+    #[test]
+    fn test_presets_share_keypad_positions() {
+        assert_eq!(Some(Binding::Keypad(Key::Key4)), Keymap::qwerty().get('q'));
+        assert_eq!(Some(Binding::Keypad(Key::Key4)), Keymap::azerty().get('a'));
+        assert_eq!(Some(Binding::Keypad(Key::KeyA)), Keymap::qwertz().get('y'));
+        assert_eq!(Some(Binding::Keypad(Key::Key4)), Keymap::dvorak().get('\''));
+        assert_eq!(Some(Binding::Keypad(Key::KeyD)), Keymap::dvorak().get('p'));
+    }
+
+    #[test]
+    fn test_lookup_ignores_case() {
+        let keymap = Keymap::qwerty();
+
+        assert_eq!(keymap.get('w'), keymap.get('W'));
+        assert_eq!(Some(Binding::Host(HostEvent::Quit)), keymap.get(ESCAPE));
+    }
+
+    #[test]
+    fn test_parse_key_name() {
+        assert_eq!(Some('x'), parse_key_name("x"));
+        assert_eq!(Some(ESCAPE), parse_key_name("Escape"));
+        assert_eq!(None, parse_key_name("ArrowUp"));
+    }
+
+    #[test]
+    fn test_config_applies_rom_overrides() {
+        let config = KeymapConfig::parse(
+            "# shared settings\n\
+             layout = azerty\n\
+             \n\
+             [PONG]\n\
+             k = 1\n\
+             a = none\n\
+             z = None\n\
+             \n\
+             [INVADERS]\n\
+             layout = dvorak\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(Binding::Keypad(Key::Key4)),
+            config.keymap_for("BRIX").get('a')
+        );
+        assert_eq!(
+            Some(Binding::Keypad(Key::Key1)),
+            config.keymap_for("PONG").get('k')
+        );
+        assert_eq!(None, config.keymap_for("PONG").get('a'));
+        assert!(config.keymap_for("BRIX").get('z').is_some());
+        assert_eq!(None, config.keymap_for("PONG").get('z'));
+        assert_eq!(&Keymap::dvorak(), config.keymap_for("INVADERS"));
+    }
+
+    #[test]
+    fn test_config_reports_line_of_error() {
+        let error = KeymapConfig::parse("layout = qwerty\nq = 10\n").unwrap_err();
+
+        assert_eq!(2, error.line);
+        assert_eq!("line 2: unknown binding `10`", error.to_string());
+    }
+}
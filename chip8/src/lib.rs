@@ -1,19 +1,50 @@
 pub mod chipset;
+pub mod disassembler;
 pub mod display;
 pub mod gpu;
+pub mod host_event;
 pub mod keyboard;
+pub mod keymap;
 pub mod opcode_processor;
+pub mod quirks;
+pub mod rom;
 
 mod memory;
 
 use chipset::PROGRAM_COUNTER_BOUNDARY;
-use chipset::{Chip8Chipset, RandomByteGenerator};
+use chipset::{
+    Chip8Chipset, CycleOutcome, DebugState, HaltReason, MachineState, PersistentFlags,
+    RandomByteGenerator,
+};
 use display::GraphicDisplay;
 use gpu::{Chip8Gpu, Gpu};
+use host_event::HostEvent;
 use keyboard::Keyboard;
-use memory::{Memory, Registers, Stack};
+use memory::{Memory, Registers, Stack, MEMORY_SIZE};
 use opcode_processor::{Chip8OpCodesProcessor, OpCodesProcessor};
-use std::result::Result;
+use quirks::Quirks;
+use std::collections::BTreeSet;
+
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+pub const FRAMES_PER_SECOND: usize = 60;
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_COUNTER_BOUNDARY as usize;
+pub const MIN_SPEED_MULTIPLIER: f32 = 0.25;
+pub const MAX_SPEED_MULTIPLIER: f32 = 8.0;
+pub const FRAME_DURATION_MS: f64 = 1000.0 / FRAMES_PER_SECOND as f64;
+/// Frames `run_for` catches up on at most, e.g. after a background tab resumes.
+pub const MAX_CATCH_UP_FRAMES: usize = 4;
+const FRAME_TOLERANCE_MS: f64 = 0.01;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct FrameSummary {
+    pub instructions_executed: usize,
+    pub screen_changed: bool,
+    pub sound_on: bool,
+    pub halted: Option<HaltReason>,
+    pub breakpoint_hit: bool,
+    pub waiting_for_key: bool,
+    pub paused: bool,
+}
 
 pub struct Emulator {
     memory: Memory,
@@ -36,6 +67,10 @@ impl Emulator {
         }
     }
 
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.opcode_processor = Chip8OpCodesProcessor::with_quirks(quirks);
+    }
+
     pub fn load_fonts(&mut self) {
         for (address, font) in self.fontset.get_values().iter().enumerate() {
             self.memory.write(address as u16, *font);
@@ -67,6 +102,7 @@ impl Emulator {
         self.load_program(data);
 
         InitializedEmulator {
+            initial_memory: self.memory.clone(),
             chipset: Chip8Chipset::new(
                 self.memory,
                 self.stack,
@@ -77,6 +113,11 @@ impl Emulator {
                 display,
                 random_byte_generator,
             ),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            speed_multiplier: 1.0,
+            paused: false,
+            saved_state: None,
+            pending_ms: 0.0,
         }
     }
 }
@@ -90,6 +131,13 @@ where
     R: RandomByteGenerator,
 {
     chipset: Chip8Chipset<O, G, K, D, R>,
+    initial_memory: Memory,
+    instructions_per_frame: usize,
+    speed_multiplier: f32,
+    paused: bool,
+    saved_state: Option<MachineState>,
+    /// Time passed to `run_for` that did not add up to a whole frame yet.
+    pending_ms: f64,
 }
 
 impl<O, G, K, D, R> InitializedEmulator<O, G, K, D, R>
@@ -100,13 +148,243 @@ where
     D: GraphicDisplay,
     R: RandomByteGenerator,
 {
-    pub fn run_cycle(&mut self) -> Result<(), String> {
+    pub fn run_cycle(&mut self) -> CycleOutcome {
+        self.process_host_events();
+
+        if self.paused && self.chipset.get_halt_reason().is_none() {
+            return CycleOutcome::Paused;
+        }
+
         self.chipset.tick()
     }
 
+    /// Runs one 60 Hz frame: executes up to `instructions_per_frame` instructions,
+    /// ticks the timers once and hands the framebuffer to the display at most once.
+    /// Hitting a breakpoint pauses, so the state there can be inspected.
+    pub fn run_frame(&mut self) -> FrameSummary {
+        self.process_host_events();
+
+        if self.paused && self.chipset.get_halt_reason().is_none() {
+            return FrameSummary {
+                instructions_executed: 0,
+                screen_changed: self.chipset.present(),
+                sound_on: false,
+                halted: None,
+                breakpoint_hit: false,
+                waiting_for_key: self.chipset.is_waiting_for_key(),
+                paused: true,
+            };
+        }
+
+        let mut instructions_executed = 0;
+        let mut halted = None;
+        let mut breakpoint_hit = false;
+        let instructions_per_frame = self.get_effective_instructions_per_frame();
+
+        while instructions_executed < instructions_per_frame {
+            match self.chipset.step() {
+                CycleOutcome::Halted(halt_reason) => {
+                    halted = Some(halt_reason);
+                    break;
+                }
+                CycleOutcome::BreakpointHit => {
+                    breakpoint_hit = true;
+                    self.paused = true;
+                    break;
+                }
+                CycleOutcome::WaitingForKey => break,
+                _ => instructions_executed += 1,
+            }
+        }
+
+        self.chipset.tick_timers();
+
+        FrameSummary {
+            instructions_executed,
+            screen_changed: self.chipset.present(),
+            sound_on: self.chipset.is_sound_on(),
+            halted,
+            breakpoint_hit,
+            waiting_for_key: self.chipset.is_waiting_for_key(),
+            paused: self.paused,
+        }
+    }
+
+    /// Runs as many frames as fit in the time elapsed since the last call,
+    /// carrying the remainder over, so that irregular timers such as
+    /// `requestAnimationFrame` still give 60 Hz. The summary covers every frame
+    /// run, stopping early on a halt or breakpoint.
+    pub fn run_for(&mut self, elapsed_ms: f64) -> FrameSummary {
+        self.pending_ms += elapsed_ms.max(0.0);
+        // Timestamps are rounded, so a frame's worth of time may come out a
+        // hair short of `FRAME_DURATION_MS`.
+        let frames_due = ((self.pending_ms + FRAME_TOLERANCE_MS) / FRAME_DURATION_MS) as usize;
+        self.pending_ms -= frames_due as f64 * FRAME_DURATION_MS;
+
+        let mut summary = FrameSummary {
+            instructions_executed: 0,
+            screen_changed: false,
+            sound_on: self.chipset.is_sound_on(),
+            halted: self.chipset.get_halt_reason(),
+            breakpoint_hit: false,
+            waiting_for_key: self.chipset.is_waiting_for_key(),
+            paused: self.paused,
+        };
+
+        for _ in 0..frames_due.min(MAX_CATCH_UP_FRAMES) {
+            let frame = self.run_frame();
+
+            summary.instructions_executed += frame.instructions_executed;
+            summary.screen_changed |= frame.screen_changed;
+            summary.sound_on = frame.sound_on;
+            summary.halted = frame.halted;
+            summary.breakpoint_hit = frame.breakpoint_hit;
+            summary.waiting_for_key = frame.waiting_for_key;
+            summary.paused = frame.paused;
+
+            if frame.halted.is_some() || frame.breakpoint_hit {
+                self.pending_ms = 0.0;
+                break;
+            }
+        }
+
+        summary
+    }
+
+    /// Applies a host request immediately, outside of the CHIP-8 keypad.
+    pub fn handle_host_event(&mut self, event: HostEvent) {
+        match event {
+            HostEvent::Quit => {
+                self.chipset.halt(HaltReason::Quit);
+            }
+            HostEvent::TogglePause => self.paused = !self.paused,
+            HostEvent::Reset => {
+                self.chipset.reset(self.initial_memory.clone());
+                self.paused = false;
+            }
+            HostEvent::SaveState => self.saved_state = Some(self.chipset.save_state()),
+            HostEvent::LoadState => {
+                if let Some(saved_state) = &self.saved_state {
+                    self.chipset.load_state(saved_state.clone());
+                }
+            }
+            HostEvent::SpeedUp => self.set_speed_multiplier(self.speed_multiplier * 2.0),
+            HostEvent::SlowDown => self.set_speed_multiplier(self.speed_multiplier / 2.0),
+            HostEvent::Redraw => self.chipset.invalidate_display(),
+            HostEvent::Step => self.step(),
+            HostEvent::ToggleBreakpoint => {
+                let program_counter = self.chipset.get_debug_state().program_counter;
+                self.chipset.toggle_breakpoint(program_counter);
+            }
+        }
+    }
+
+    /// Executes the instruction at the program counter, even if a breakpoint
+    /// sits there. Only has an effect while paused.
+    fn step(&mut self) {
+        if !self.paused {
+            return;
+        }
+
+        if self.chipset.step() == CycleOutcome::BreakpointHit {
+            self.chipset.step();
+        }
+        self.chipset.present();
+    }
+
+    fn process_host_events(&mut self) {
+        while let Some(event) = self.chipset.poll_host_event() {
+            self.handle_host_event(event);
+        }
+    }
+
+    pub fn save_state(&self) -> MachineState {
+        self.chipset.save_state()
+    }
+
+    pub fn load_state(&mut self, machine_state: MachineState) {
+        self.chipset.load_state(machine_state);
+    }
+
+    /// The state captured by the last `HostEvent::SaveState`, if any.
+    pub fn get_saved_state(&self) -> Option<&MachineState> {
+        self.saved_state.as_ref()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Also changes in cycles reported as `CycleOutcome::Drew`.
+    pub fn is_sound_on(&self) -> bool {
+        self.chipset.is_sound_on()
+    }
+
+    pub fn set_speed_multiplier(&mut self, speed_multiplier: f32) {
+        self.speed_multiplier = speed_multiplier.clamp(MIN_SPEED_MULTIPLIER, MAX_SPEED_MULTIPLIER);
+    }
+
+    pub fn get_speed_multiplier(&self) -> f32 {
+        self.speed_multiplier
+    }
+
+    fn get_effective_instructions_per_frame(&self) -> usize {
+        let instructions = self.instructions_per_frame as f32 * self.speed_multiplier;
+
+        (instructions.round() as usize).max(1)
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.chipset.is_waiting_for_key()
+    }
+
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        self.chipset.toggle_breakpoint(address)
+    }
+
+    pub fn get_breakpoints(&self) -> &BTreeSet<u16> {
+        self.chipset.get_breakpoints()
+    }
+
+    pub fn get_debug_state(&self) -> DebugState {
+        self.chipset.get_debug_state()
+    }
+
+    pub fn read_memory(&self, address: u16, length: usize) -> Vec<u8> {
+        self.chipset.read_memory(address, length)
+    }
+
+    pub fn get_persistent_flags(&self) -> &PersistentFlags {
+        self.chipset.get_persistent_flags()
+    }
+
+    pub fn set_persistent_flags(&mut self, persistent_flags: PersistentFlags) {
+        self.chipset.set_persistent_flags(persistent_flags);
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: usize) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    pub fn get_instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
     pub fn get_keyboard(&self) -> &K {
         self.chipset.get_keyboard()
     }
+
+    pub fn get_keyboard_mut(&mut self) -> &mut K {
+        self.chipset.get_keyboard_mut()
+    }
+
+    pub fn get_display(&self) -> &D {
+        self.chipset.get_display()
+    }
+
+    pub fn get_display_mut(&mut self) -> &mut D {
+        self.chipset.get_display_mut()
+    }
 }
 
 struct Fontset {
@@ -144,34 +422,51 @@ impl Fontset {
 
 #[cfg(test)]
 mod test_emulator {
-    use super::{Emulator, Fontset};
-    use crate::chipset::RandomByteGenerator;
-    use crate::display::GraphicDisplay;
+    use super::{Emulator, Fontset, FrameSummary, MAX_CATCH_UP_FRAMES};
+    use crate::chipset::{CycleOutcome, HaltReason, RandomByteGenerator};
+    use crate::display::{DirtyRegion, GraphicDisplay};
     use crate::gpu::Chip8Gpu;
-    use crate::keyboard::{Key, Keyboard};
+    use crate::host_event::HostEvent;
+    use crate::keyboard::Keyboard;
     use crate::memory::{Memory, Registers, Stack};
     use crate::opcode_processor::Chip8OpCodesProcessor;
 
     use rand;
     use std::ops;
 
-    struct MockedKeyboard {}
+    struct MockedKeyboard {
+        host_events: Vec<HostEvent>,
+    }
+    impl MockedKeyboard {
+        fn new() -> Self {
+            MockedKeyboard {
+                host_events: Vec::new(),
+            }
+        }
+    }
     impl Keyboard for MockedKeyboard {
-        fn get_pressed_key(&mut self) -> Option<Key> {
-            None
+        fn get_held_keys(&mut self) -> u16 {
+            0
         }
 
-        fn wait_for_key_press(&mut self) -> Key {
-            Key::Key0
+        fn poll_host_event(&mut self) -> Option<HostEvent> {
+            if self.host_events.is_empty() {
+                None
+            } else {
+                Some(self.host_events.remove(0))
+            }
         }
     }
 
-    struct MocketDisplay {}
+    struct MocketDisplay {
+        draw_calls: usize,
+    }
     impl GraphicDisplay for MocketDisplay {
-        fn draw<M>(&mut self, _: &M)
+        fn draw<M>(&mut self, _: &M, _: &[DirtyRegion])
         where
             M: ops::Index<usize, Output = [u8]>,
         {
+            self.draw_calls += 1;
         }
     }
 
@@ -182,24 +477,292 @@ mod test_emulator {
         }
     }
 
-    #[test]
-    fn test_can_run_program() {
-        let emulator = Emulator {
+    fn create_emulator() -> Emulator {
+        Emulator {
             memory: Memory::new(),
             stack: Stack::new(),
             fontset: Fontset::new(),
             registers: Registers::new(),
             gpu: Chip8Gpu::new(),
             opcode_processor: Chip8OpCodesProcessor::new(),
-        };
+        }
+    }
+
+    #[test]
+    fn test_can_run_program() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x00, 0xe0],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
 
-        let mut initialized_emulator = emulator.initialize(
+        assert_eq!(CycleOutcome::Drew, initialized_emulator.run_cycle());
+        assert_eq!(
+            CycleOutcome::Halted(HaltReason::EndOfProgram),
+            initialized_emulator.run_cycle()
+        );
+    }
+
+    #[test]
+    fn test_run_frame_presents_framebuffer_once() {
+        // Draws the font sprite for "0" twice, sets the sound timer and jumps in place.
+        let program = [0xd0, 0x05, 0xd0, 0x05, 0x60, 0x05, 0xf0, 0x18, 0x12, 0x08];
+        let mut initialized_emulator = create_emulator().initialize(
+            &program,
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        initialized_emulator.set_instructions_per_frame(8);
+
+        assert_eq!(
+            FrameSummary {
+                instructions_executed: 8,
+                screen_changed: true,
+                sound_on: true,
+                halted: None,
+                breakpoint_hit: false,
+                waiting_for_key: false,
+                paused: false,
+            },
+            initialized_emulator.run_frame()
+        );
+        assert_eq!(1, initialized_emulator.chipset.get_display().draw_calls);
+
+        assert_eq!(
+            FrameSummary {
+                instructions_executed: 8,
+                screen_changed: false,
+                sound_on: true,
+                halted: None,
+                breakpoint_hit: false,
+                waiting_for_key: false,
+                paused: false,
+            },
+            initialized_emulator.run_frame()
+        );
+        assert_eq!(1, initialized_emulator.chipset.get_display().draw_calls);
+    }
+
+    #[test]
+    fn test_run_frame_reports_halt() {
+        let mut initialized_emulator = create_emulator().initialize(
             &[0x00, 0xe0],
-            MockedKeyboard {},
-            MocketDisplay {},
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        let summary = initialized_emulator.run_frame();
+
+        assert_eq!(1, summary.instructions_executed);
+        assert!(summary.screen_changed);
+        assert_eq!(Some(HaltReason::EndOfProgram), summary.halted);
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_breakpoint() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x60, 0x01, 0x60, 0x02, 0x12, 0x00],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        initialized_emulator.toggle_breakpoint(0x202);
+
+        let summary = initialized_emulator.run_frame();
+        assert_eq!(1, summary.instructions_executed);
+        assert!(summary.breakpoint_hit);
+        assert!(summary.paused);
+
+        let summary = initialized_emulator.run_frame();
+        assert_eq!(0, summary.instructions_executed);
+        assert!(summary.paused);
+
+        initialized_emulator.handle_host_event(HostEvent::TogglePause);
+        let summary = initialized_emulator.run_frame();
+        assert_eq!(3, summary.instructions_executed);
+        assert!(summary.breakpoint_hit);
+        assert!(summary.paused);
+    }
+
+    #[test]
+    fn test_run_frame_ends_early_while_waiting_for_key() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x60, 0x01, 0xf0, 0x0a, 0x12, 0x04],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        let summary = initialized_emulator.run_frame();
+        assert_eq!(2, summary.instructions_executed);
+        assert!(summary.waiting_for_key);
+
+        let summary = initialized_emulator.run_frame();
+        assert_eq!(0, summary.instructions_executed);
+        assert!(initialized_emulator.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_quit_event_halts_emulator() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x12, 0x00],
+            MockedKeyboard {
+                host_events: vec![HostEvent::Quit],
+            },
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        assert_eq!(
+            Some(HaltReason::Quit),
+            initialized_emulator.run_frame().halted
+        );
+    }
+
+    #[test]
+    fn test_paused_emulator_does_not_execute() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x12, 0x00],
+            MockedKeyboard {
+                host_events: vec![HostEvent::TogglePause],
+            },
+            MocketDisplay { draw_calls: 0 },
             TestRandomByteGenerator {},
         );
 
-        while let Ok(()) = initialized_emulator.run_cycle() {}
+        let summary = initialized_emulator.run_frame();
+        assert!(summary.paused);
+        assert_eq!(0, summary.instructions_executed);
+        assert_eq!(CycleOutcome::Paused, initialized_emulator.run_cycle());
+
+        initialized_emulator.handle_host_event(HostEvent::TogglePause);
+        assert_eq!(CycleOutcome::Executed, initialized_emulator.run_cycle());
+    }
+
+    #[test]
+    fn test_reset_restarts_program() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x00, 0xe0],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        initialized_emulator.run_frame();
+
+        initialized_emulator.handle_host_event(HostEvent::Reset);
+
+        assert_eq!(CycleOutcome::Drew, initialized_emulator.run_cycle());
+    }
+
+    #[test]
+    fn test_load_state_restores_saved_state() {
+        // Counts V0 up forever.
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x70, 0x01, 0x12, 0x00],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        initialized_emulator.run_cycle();
+        initialized_emulator.handle_host_event(HostEvent::SaveState);
+        let saved_state = initialized_emulator.chipset.save_state();
+
+        initialized_emulator.run_frame();
+        initialized_emulator.handle_host_event(HostEvent::LoadState);
+
+        assert!(saved_state == initialized_emulator.chipset.save_state());
+    }
+
+    #[test]
+    fn test_redraw_presents_unchanged_screen_again() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x12, 0x00],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        assert!(!initialized_emulator.run_frame().screen_changed);
+
+        initialized_emulator.handle_host_event(HostEvent::Redraw);
+
+        assert!(initialized_emulator.run_frame().screen_changed);
+        assert_eq!(1, initialized_emulator.chipset.get_display().draw_calls);
+    }
+
+    #[test]
+    fn test_speed_changes_instructions_per_frame() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x12, 0x00],
+            MockedKeyboard {
+                host_events: vec![HostEvent::SpeedUp],
+            },
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        assert_eq!(20, initialized_emulator.run_frame().instructions_executed);
+
+        for _ in 0..10 {
+            initialized_emulator.handle_host_event(HostEvent::SlowDown);
+        }
+        assert_eq!(0.25, initialized_emulator.get_speed_multiplier());
+        assert_eq!(3, initialized_emulator.run_frame().instructions_executed);
+    }
+
+    #[test]
+    fn test_run_for_runs_frames_for_elapsed_time() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x12, 0x00],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        assert_eq!(0, initialized_emulator.run_for(10.0).instructions_executed);
+        assert_eq!(10, initialized_emulator.run_for(10.0).instructions_executed);
+        assert_eq!(20, initialized_emulator.run_for(33.4).instructions_executed);
+        assert_eq!(
+            MAX_CATCH_UP_FRAMES * 10,
+            initialized_emulator.run_for(10_000.0).instructions_executed
+        );
+    }
+
+    #[test]
+    fn test_run_for_stops_at_halt() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x00, 0xe0],
+            MockedKeyboard::new(),
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+
+        let summary = initialized_emulator.run_for(50.0);
+
+        assert_eq!(1, summary.instructions_executed);
+        assert_eq!(Some(HaltReason::EndOfProgram), summary.halted);
+    }
+
+    #[test]
+    fn test_step_executes_one_instruction_while_paused() {
+        let mut initialized_emulator = create_emulator().initialize(
+            &[0x60, 0x01, 0x60, 0x02, 0x12, 0x00],
+            MockedKeyboard {
+                host_events: vec![HostEvent::TogglePause, HostEvent::ToggleBreakpoint],
+            },
+            MocketDisplay { draw_calls: 0 },
+            TestRandomByteGenerator {},
+        );
+        initialized_emulator.run_frame();
+        assert!(initialized_emulator.get_breakpoints().contains(&0x200));
+
+        initialized_emulator.handle_host_event(HostEvent::Step);
+        initialized_emulator.handle_host_event(HostEvent::Step);
+
+        let debug_state = initialized_emulator.get_debug_state();
+        assert_eq!(0x204, debug_state.program_counter);
+        assert_eq!(2, debug_state.registers[0]);
     }
 }
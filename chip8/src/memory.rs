@@ -1,7 +1,8 @@
 pub const MEMORY_SIZE: usize = 0x1000;
-const STACK_SIZE: usize = 0xf;
-const REGISTERS_COUNT: usize = 0x10;
+pub const STACK_SIZE: usize = 0xf;
+pub const REGISTERS_COUNT: usize = 0x10;
 
+#[derive(Clone, PartialEq)]
 pub struct Memory {
     memory: [u8; MEMORY_SIZE],
 }
@@ -22,6 +23,7 @@ impl Memory {
     }
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Stack {
     memory: [u16; STACK_SIZE],
     stack_pointer: usize,
@@ -45,8 +47,22 @@ impl Stack {
         self.memory[self.stack_pointer] = address;
         self.stack_pointer += 1;
     }
+
+    pub fn get_frames(&self) -> &[u16] {
+        &self.memory
+    }
+
+    pub fn get_stack_pointer(&self) -> usize {
+        self.stack_pointer
+    }
+
+    pub fn restore(&mut self, frames: &[u16], stack_pointer: usize) {
+        self.memory.copy_from_slice(frames);
+        self.stack_pointer = stack_pointer;
+    }
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Registers {
     registers: [u8; REGISTERS_COUNT],
 }
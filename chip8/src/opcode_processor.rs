@@ -1,7 +1,8 @@
-use crate::chipset::{RandomByteGenerator, INSTRUCTION_SIZE};
+use crate::chipset::{ChipsetState, PersistentFlags, RandomByteGenerator, INSTRUCTION_SIZE};
 use crate::gpu::Gpu;
-use crate::keyboard::{Key, Keyboard};
+use crate::keyboard::{Key, Keypad};
 use crate::memory::{Memory, Registers, Stack};
+use crate::quirks::Quirks;
 
 use std::fmt;
 use std::result;
@@ -28,6 +29,10 @@ impl OpCode {
         }
     }
 
+    pub fn get_data(&self) -> u16 {
+        self.opcode
+    }
+
     pub fn get_parts(&self) -> (u8, u8, u8, u8) {
         (((self.opcode & 0xf000) >> 12) as u8, self.x, self.y, self.n)
     }
@@ -80,9 +85,9 @@ pub trait OpCodesProcessor {
     fn bitop_vx_equal_vx_xor_vy(&self, registers: &mut Registers, x: u8, y: u8);
     fn math_vx_equal_vx_plus_vy(&self, registers: &mut Registers, x: u8, y: u8);
     fn math_vx_equal_vx_minus_vy(&self, registers: &mut Registers, x: u8, y: u8);
-    fn bitop_vx_equal_vx_shr(&self, registers: &mut Registers, x: u8);
+    fn bitop_vx_equal_vx_shr(&self, registers: &mut Registers, x: u8, y: u8);
     fn math_vx_equal_vy_minus_vx(&self, registers: &mut Registers, x: u8, y: u8);
-    fn bitop_vx_equal_vx_shl(&self, registers: &mut Registers, x: u8);
+    fn bitop_vx_equal_vx_shl(&self, registers: &mut Registers, x: u8, y: u8);
     fn cond_vx_not_equal_vy(&self, registers: &Registers, program_counter: &mut u16, x: u8, y: u8);
     fn mem_i_equal_nnn(&self, address_register: &mut u16, nnn: u16);
     fn flow_pc_equal_v0_plus_nnn(&self, program_counter: &mut u16, nnn: u16, registers: &Registers);
@@ -111,50 +116,63 @@ pub trait OpCodesProcessor {
         &self,
         registers: &Registers,
         memory: &mut Memory,
-        address_register: u16,
+        address_register: &mut u16,
         x: u8,
     );
     fn mem_reg_load(
         &self,
         registers: &mut Registers,
         memory: &Memory,
-        address_register: u16,
+        address_register: &mut u16,
         x: u8,
     );
-    fn keyop_if_key_equal_vx<K>(
+    fn flags_dump(&self, registers: &Registers, flags: &mut PersistentFlags, x: u8);
+    fn flags_load(&self, registers: &mut Registers, flags: &PersistentFlags, x: u8);
+    fn keyop_if_key_equal_vx(
         &self,
-        keyboard: &mut K,
+        keypad: &Keypad,
         registers: &Registers,
         program_counter: &mut u16,
         x: u8,
-    ) where
-        K: Keyboard;
-    fn keyop_if_key_not_equal_vx<K>(
+    );
+    fn keyop_if_key_not_equal_vx(
         &self,
-        keyboard: &mut K,
+        keypad: &Keypad,
         registers: &Registers,
         program_counter: &mut u16,
         x: u8,
-    ) where
-        K: Keyboard;
-    fn keyop_vx_equal_key<K>(
-        &self,
-        keyboard: &mut K,
-        registers: &mut Registers,
-        x: u8,
-        program_counter: &mut u16,
-    ) where
-        K: Keyboard;
+    );
+    fn keyop_vx_equal_key(&self, state: &mut ChipsetState, x: u8);
     fn timer_vx_equal_get_delay(&self, delay_timer: u8, registers: &mut Registers, x: u8);
     fn timer_delay_timer_equal_vx(&self, delay_timer: &mut u8, registers: &Registers, x: u8);
-    fn sound_sound_timer_equal_vx(&self);
+    fn sound_sound_timer_equal_vx(&self, sound_timer: &mut u8, registers: &Registers, x: u8);
 }
 
-pub struct Chip8OpCodesProcessor {}
+pub struct Chip8OpCodesProcessor {
+    quirks: Quirks,
+}
 
 impl Chip8OpCodesProcessor {
     pub fn new() -> Self {
-        Chip8OpCodesProcessor {}
+        Chip8OpCodesProcessor::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Chip8OpCodesProcessor { quirks }
+    }
+
+    fn shift_source(&self, registers: &Registers, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            registers.get_register_at(y as usize)
+        } else {
+            registers.get_register_at(x as usize)
+        }
+    }
+
+    fn reset_vf_after_logic(&self, registers: &mut Registers) {
+        if self.quirks.logic_resets_vf {
+            registers.set_register_at(0xf, 0x0);
+        }
     }
 }
 
@@ -222,6 +240,7 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         let vy = registers.get_register_at(y as usize);
 
         registers.set_register_at(x as usize, vx | vy);
+        self.reset_vf_after_logic(registers);
     }
 
     fn bitop_vx_equal_vx_and_vy(&self, registers: &mut Registers, x: u8, y: u8) {
@@ -229,6 +248,7 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         let vy = registers.get_register_at(y as usize);
 
         registers.set_register_at(x as usize, vx & vy);
+        self.reset_vf_after_logic(registers);
     }
 
     fn bitop_vx_equal_vx_xor_vy(&self, registers: &mut Registers, x: u8, y: u8) {
@@ -236,6 +256,7 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         let vy = registers.get_register_at(y as usize);
 
         registers.set_register_at(x as usize, vx ^ vy);
+        self.reset_vf_after_logic(registers);
     }
 
     fn math_vx_equal_vx_plus_vy(&self, registers: &mut Registers, x: u8, y: u8) {
@@ -264,8 +285,8 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         }
     }
 
-    fn bitop_vx_equal_vx_shr(&self, registers: &mut Registers, x: u8) {
-        let vx = registers.get_register_at(x as usize);
+    fn bitop_vx_equal_vx_shr(&self, registers: &mut Registers, x: u8, y: u8) {
+        let vx = self.shift_source(registers, x, y);
 
         registers.set_register_at(0xf, vx & 0b0000_0001);
         registers.set_register_at(x as usize, vx >> 1);
@@ -284,8 +305,8 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         }
     }
 
-    fn bitop_vx_equal_vx_shl(&self, registers: &mut Registers, x: u8) {
-        let vx = registers.get_register_at(x as usize);
+    fn bitop_vx_equal_vx_shl(&self, registers: &mut Registers, x: u8, y: u8) {
+        let vx = self.shift_source(registers, x, y);
 
         if vx & 0b1000_0000 == 0b1000_0000 {
             registers.set_register_at(0xf, 0x1);
@@ -312,7 +333,13 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         nnn: u16,
         registers: &Registers,
     ) {
-        *program_counter = nnn + u16::from(registers.get_register_at(0));
+        let register = if self.quirks.jump_uses_vx {
+            (nnn >> 8) as usize
+        } else {
+            0
+        };
+
+        *program_counter = nnn + u16::from(registers.get_register_at(register));
     }
 
     fn rand_vx_equal_rand_and_nn(
@@ -374,90 +401,85 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         &self,
         registers: &Registers,
         memory: &mut Memory,
-        address_register: u16,
+        address_register: &mut u16,
         x: u8,
     ) {
-        let mut counter = address_register;
+        let mut counter = *address_register;
         for z in 0x0..=x {
             memory.write(counter, registers.get_register_at(z as usize));
             counter += 1;
         }
+
+        if self.quirks.load_store_increments_i {
+            *address_register = counter;
+        }
     }
 
     fn mem_reg_load(
         &self,
         registers: &mut Registers,
         memory: &Memory,
-        address_register: u16,
+        address_register: &mut u16,
         x: u8,
     ) {
-        let mut counter = address_register;
+        let mut counter = *address_register;
         for z in 0x0..=x {
             registers.set_register_at(z as usize, memory.read(counter));
             counter += 1;
         }
+
+        if self.quirks.load_store_increments_i {
+            *address_register = counter;
+        }
     }
 
-    fn keyop_if_key_equal_vx<K>(
-        &self,
-        keyboard: &mut K,
-        registers: &Registers,
-        program_counter: &mut u16,
-        x: u8,
-    ) where
-        K: Keyboard,
-    {
-        if let Some(key) = keyboard.get_pressed_key() {
-            match key {
-                Key::KeyESC => *program_counter = u16::max_value() - 2,
-                key => {
-                    if registers.get_register_at(x as usize) == key as u8 {
-                        *program_counter += INSTRUCTION_SIZE;
-                    }
-                }
-            }
+    /// SUPER-CHIP only has 8 flags, so registers past V7 are left out.
+    fn flags_dump(&self, registers: &Registers, flags: &mut PersistentFlags, x: u8) {
+        for (z, flag) in flags.iter_mut().enumerate().take(x as usize + 1) {
+            *flag = registers.get_register_at(z);
         }
     }
 
-    fn keyop_if_key_not_equal_vx<K>(
+    fn flags_load(&self, registers: &mut Registers, flags: &PersistentFlags, x: u8) {
+        for (z, flag) in flags.iter().enumerate().take(x as usize + 1) {
+            registers.set_register_at(z, *flag);
+        }
+    }
+
+    fn keyop_if_key_equal_vx(
         &self,
-        keyboard: &mut K,
+        keypad: &Keypad,
         registers: &Registers,
         program_counter: &mut u16,
         x: u8,
-    ) where
-        K: Keyboard,
-    {
-        match keyboard.get_pressed_key() {
-            Some(key) => match key {
-                Key::KeyESC => *program_counter = u16::max_value() - 2,
-                key => {
-                    if registers.get_register_at(x as usize) != key as u8 {
-                        *program_counter += INSTRUCTION_SIZE;
-                    }
-                }
-            },
-            None => {
+    ) {
+        if let Some(key) = Key::from_index(registers.get_register_at(x as usize)) {
+            if keypad.is_held(key) {
                 *program_counter += INSTRUCTION_SIZE;
             }
         }
     }
 
-    fn keyop_vx_equal_key<K>(
+    fn keyop_if_key_not_equal_vx(
         &self,
-        keyboard: &mut K,
-        registers: &mut Registers,
-        x: u8,
+        keypad: &Keypad,
+        registers: &Registers,
         program_counter: &mut u16,
-    ) where
-        K: Keyboard,
-    {
-        match keyboard.wait_for_key_press() {
-            Key::KeyESC => *program_counter = u16::max_value() - 2,
-            key => registers.set_register_at(x as usize, key as u8),
+        x: u8,
+    ) {
+        match Key::from_index(registers.get_register_at(x as usize)) {
+            Some(key) if keypad.is_held(key) => {}
+            _ => *program_counter += INSTRUCTION_SIZE,
         }
     }
 
+    fn keyop_vx_equal_key(&self, state: &mut ChipsetState, x: u8) {
+        *state = ChipsetState::WaitingForKey {
+            x,
+            pressed_key: None,
+        };
+    }
+
     fn timer_vx_equal_get_delay(&self, delay_timer: u8, registers: &mut Registers, x: u8) {
         registers.set_register_at(x as usize, delay_timer);
     }
@@ -466,8 +488,8 @@ impl OpCodesProcessor for Chip8OpCodesProcessor {
         *delay_timer = registers.get_register_at(x as usize);
     }
 
-    fn sound_sound_timer_equal_vx(&self) {
-        //TODO Implement
+    fn sound_sound_timer_equal_vx(&self, sound_timer: &mut u8, registers: &Registers, x: u8) {
+        *sound_timer = registers.get_register_at(x as usize);
     }
 }
 
@@ -521,8 +543,10 @@ mod test_opcode {
 #[cfg(test)]
 mod test_opcodes_processor {
     use super::*;
+    use crate::chipset::PERSISTENT_FLAGS_COUNT;
+    use crate::display::DirtyRegion;
     use crate::gpu::{Gpu, GraphicMemory};
-    use crate::keyboard::{Key, Keyboard};
+    use crate::keyboard::{Key, Keypad};
     use crate::memory::{Memory, Registers, Stack};
     use rand;
 
@@ -569,17 +593,21 @@ mod test_opcodes_processor {
         fn get_memory<'a>(&'a self) -> &GraphicMemory {
             &self.graphic_memory
         }
-    }
 
-    struct MockedKeyboard;
-    impl Keyboard for MockedKeyboard {
-        fn wait_for_key_press(&mut self) -> Key {
-            Key::Key5
+        fn take_dirty_regions(&mut self) -> Vec<DirtyRegion> {
+            Vec::new()
         }
 
-        fn get_pressed_key(&mut self) -> Option<Key> {
-            Some(Key::Key4)
-        }
+        fn load_memory(&mut self, _memory: &GraphicMemory) {}
+
+        fn invalidate(&mut self) {}
+    }
+
+    fn create_keypad() -> Keypad {
+        let mut keypad = Keypad::new();
+        keypad.update(Key::Key4.mask() | Key::Key6.mask());
+
+        keypad
     }
 
     struct TestRandomByteGenerator {}
@@ -894,7 +922,7 @@ mod test_opcodes_processor {
         registers.set_register_at(x as usize, before);
         registers.set_register_at(0xf, 0x1);
 
-        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shr(&mut registers, x);
+        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shr(&mut registers, x, 0x2);
 
         assert_eq!(after, registers.get_register_at(x as usize));
         assert_eq!(0x0, registers.get_register_at(0xf as usize));
@@ -910,7 +938,7 @@ mod test_opcodes_processor {
         registers.set_register_at(x as usize, before);
         registers.set_register_at(0xf, 0x0);
 
-        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shr(&mut registers, x);
+        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shr(&mut registers, x, 0x2);
 
         assert_eq!(after, registers.get_register_at(x as usize));
         assert_eq!(0x1, registers.get_register_at(0xf as usize));
@@ -958,7 +986,7 @@ mod test_opcodes_processor {
         registers.set_register_at(x as usize, before);
         registers.set_register_at(0xf, 0x0);
 
-        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shl(&mut registers, x);
+        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shl(&mut registers, x, 0x2);
 
         assert_eq!(after, registers.get_register_at(x as usize));
         assert_eq!(0x1, registers.get_register_at(0xf as usize));
@@ -974,7 +1002,7 @@ mod test_opcodes_processor {
         registers.set_register_at(x as usize, before);
         registers.set_register_at(0xf, 0x1);
 
-        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shl(&mut registers, x);
+        Chip8OpCodesProcessor::new().bitop_vx_equal_vx_shl(&mut registers, x, 0x2);
 
         assert_eq!(after, registers.get_register_at(x as usize));
         assert_eq!(0x0, registers.get_register_at(0xf as usize));
@@ -1039,6 +1067,55 @@ mod test_opcodes_processor {
         assert_eq!(0x2ff, program_counter);
     }
 
+    #[test]
+    fn test_flow_pc_equal_vx_plus_nnn_with_jump_quirk() {
+        let mut program_counter: u16 = 0x100;
+
+        let mut registers = Registers::new();
+        registers.set_register_at(0, 0xff);
+        registers.set_register_at(2, 0x1);
+
+        Chip8OpCodesProcessor::with_quirks(Quirks::schip()).flow_pc_equal_v0_plus_nnn(
+            &mut program_counter,
+            0x234,
+            &registers,
+        );
+
+        assert_eq!(0x235, program_counter);
+    }
+
+    #[test]
+    fn test_bitop_vx_equal_vx_shr_with_shift_quirk() {
+        let (x, y) = (0x1, 0x2);
+
+        let mut registers = Registers::new();
+        registers.set_register_at(x as usize, 0b1000_0000);
+        registers.set_register_at(y as usize, 0b0000_0011);
+
+        Chip8OpCodesProcessor::with_quirks(Quirks::cosmac()).bitop_vx_equal_vx_shr(
+            &mut registers,
+            x,
+            y,
+        );
+
+        assert_eq!(0b0000_0001, registers.get_register_at(x as usize));
+        assert_eq!(0x1, registers.get_register_at(0xf));
+    }
+
+    #[test]
+    fn test_bitop_vx_equal_vx_or_vy_with_vf_reset_quirk() {
+        let mut registers = Registers::new();
+        registers.set_register_at(0xf, 0x1);
+
+        Chip8OpCodesProcessor::with_quirks(Quirks::cosmac()).bitop_vx_equal_vx_or_vy(
+            &mut registers,
+            0x1,
+            0x2,
+        );
+
+        assert_eq!(0x0, registers.get_register_at(0xf));
+    }
+
     #[test]
     fn test_rand_vx_equal_rand_and_nn() {
         let x: u8 = 0x1;
@@ -1147,7 +1224,7 @@ mod test_opcodes_processor {
         let x: u8 = 0xf;
         let mut memory = Memory::new();
         let mut registers = Registers::new();
-        let address_register: u16 = 0x200;
+        let mut address_register: u16 = 0x200;
 
         let range = (0x0..=0xf).collect::<Vec<u8>>();
 
@@ -1155,19 +1232,58 @@ mod test_opcodes_processor {
             registers.set_register_at(*i as usize, i + 5);
         }
 
-        Chip8OpCodesProcessor::new().mem_reg_dump(&registers, &mut memory, address_register, x);
+        Chip8OpCodesProcessor::new().mem_reg_dump(
+            &registers,
+            &mut memory,
+            &mut address_register,
+            x,
+        );
 
         for i in range {
             assert_eq!(i + 5, memory.read(address_register + u16::from(i)));
         }
     }
 
+    #[test]
+    fn test_flags_dump_and_load() {
+        let processor = Chip8OpCodesProcessor::new();
+        let mut registers = Registers::new();
+        let mut flags = [0; PERSISTENT_FLAGS_COUNT];
+        for i in 0..0x10 {
+            registers.set_register_at(i, i as u8 + 1);
+        }
+
+        processor.flags_dump(&registers, &mut flags, 0xf);
+        assert_eq!([1, 2, 3, 4, 5, 6, 7, 8], flags);
+
+        let mut registers = Registers::new();
+        processor.flags_load(&mut registers, &flags, 0x2);
+        assert_eq!(3, registers.get_register_at(2));
+        assert_eq!(0, registers.get_register_at(3));
+    }
+
+    #[test]
+    fn test_mem_reg_dump_with_load_store_quirk() {
+        let mut memory = Memory::new();
+        let registers = Registers::new();
+        let mut address_register: u16 = 0x300;
+
+        Chip8OpCodesProcessor::with_quirks(Quirks::cosmac()).mem_reg_dump(
+            &registers,
+            &mut memory,
+            &mut address_register,
+            0x3,
+        );
+
+        assert_eq!(0x304, address_register);
+    }
+
     #[test]
     fn test_mem_reg_load() {
         let x: u8 = 0xf;
         let mut memory = Memory::new();
         let mut registers = Registers::new();
-        let address_register: u16 = 0x200;
+        let mut address_register: u16 = 0x200;
 
         let range = (address_register..=(address_register + u16::from(x))).collect::<Vec<u16>>();
 
@@ -1175,7 +1291,12 @@ mod test_opcodes_processor {
             memory.write(*address, i as u8);
         }
 
-        Chip8OpCodesProcessor::new().mem_reg_load(&mut registers, &memory, address_register, x);
+        Chip8OpCodesProcessor::new().mem_reg_load(
+            &mut registers,
+            &memory,
+            &mut address_register,
+            x,
+        );
 
         for (i, _) in range.iter().enumerate() {
             assert_eq!(i as u8, registers.get_register_at(i));
@@ -1230,54 +1351,84 @@ mod test_opcodes_processor {
 
     #[test]
     fn test_keyop_vx_equal_key() {
-        let mut keyboard = MockedKeyboard {};
-        let mut registers = Registers::new();
-        let mut program_counter = 0;
+        let mut state = ChipsetState::Running;
 
-        Chip8OpCodesProcessor::new().keyop_vx_equal_key(
-            &mut keyboard,
-            &mut registers,
-            0x1,
-            &mut program_counter,
-        );
+        Chip8OpCodesProcessor::new().keyop_vx_equal_key(&mut state, 0x1);
 
-        assert_eq!(0x5, registers.get_register_at(0x1));
+        assert_eq!(
+            ChipsetState::WaitingForKey {
+                x: 0x1,
+                pressed_key: None
+            },
+            state
+        );
     }
 
     #[test]
     fn test_keyop_if_key_equal_vx() {
-        let mut keyboard = MockedKeyboard {};
+        let keypad = create_keypad();
         let mut registers = Registers::new();
         let mut program_counter = 0x0;
 
         registers.set_register_at(0x1, 0x4);
 
         Chip8OpCodesProcessor::new().keyop_if_key_equal_vx(
-            &mut keyboard,
-            &mut registers,
+            &keypad,
+            &registers,
             &mut program_counter,
             0x1,
         );
 
         assert_eq!(0x2, program_counter);
+
+        registers.set_register_at(0x1, 0x6);
+
+        Chip8OpCodesProcessor::new().keyop_if_key_equal_vx(
+            &keypad,
+            &registers,
+            &mut program_counter,
+            0x1,
+        );
+
+        assert_eq!(0x4, program_counter);
     }
 
     #[test]
     fn test_keyop_if_key_not_equal_vx() {
-        let mut keyboard = MockedKeyboard {};
+        let keypad = create_keypad();
         let mut registers = Registers::new();
         let mut program_counter = 0x0;
 
         registers.set_register_at(0x1, 0x5);
 
         Chip8OpCodesProcessor::new().keyop_if_key_equal_vx(
-            &mut keyboard,
-            &mut registers,
+            &keypad,
+            &registers,
             &mut program_counter,
             0x1,
         );
 
         assert_eq!(0x0, program_counter);
+
+        Chip8OpCodesProcessor::new().keyop_if_key_not_equal_vx(
+            &keypad,
+            &registers,
+            &mut program_counter,
+            0x1,
+        );
+
+        assert_eq!(0x2, program_counter);
+
+        registers.set_register_at(0x1, 0x6);
+
+        Chip8OpCodesProcessor::new().keyop_if_key_not_equal_vx(
+            &keypad,
+            &registers,
+            &mut program_counter,
+            0x1,
+        );
+
+        assert_eq!(0x2, program_counter);
     }
 
     #[test]
@@ -1301,4 +1452,16 @@ mod test_opcodes_processor {
 
         assert_eq!(0x30, delay_timer);
     }
+
+    #[test]
+    fn test_sound_sound_timer_equal_vx() {
+        let mut sound_timer = 0x0;
+        let mut registers = Registers::new();
+
+        registers.set_register_at(0xb, 0x10);
+
+        Chip8OpCodesProcessor::new().sound_sound_timer_equal_vx(&mut sound_timer, &registers, 0xb);
+
+        assert_eq!(0x10, sound_timer);
+    }
 }
@@ -0,0 +1,62 @@
+pub const PROFILES: [&str; 3] = ["modern", "cosmac", "schip"];
+
+/// Behaviours that differ between CHIP-8 interpreters. ROMs written for one
+/// interpreter often misbehave on another unless these match.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55 and FX65 leave I pointing past the last register they touched.
+    pub load_store_increments_i: bool,
+    /// 8XY1, 8XY2 and 8XY3 clear VF.
+    pub logic_resets_vf: bool,
+    /// BXNN jumps to XNN plus VX rather than NNN plus V0.
+    pub jump_uses_vx: bool,
+}
+
+impl Quirks {
+    /// What most ROMs written in the last decade expect.
+    pub fn modern() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            jump_uses_vx: false,
+        }
+    }
+
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            logic_resets_vf: true,
+            jump_uses_vx: false,
+        }
+    }
+
+    /// SUPER-CHIP on the HP48 calculators.
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+        }
+    }
+
+    pub fn from_profile(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "modern" => Some(Quirks::modern()),
+            "cosmac" | "chip8" | "vip" => Some(Quirks::cosmac()),
+            "schip" | "superchip" => Some(Quirks::schip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
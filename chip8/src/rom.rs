@@ -0,0 +1,92 @@
+use crate::quirks::Quirks;
+use crate::MAX_PROGRAM_SIZE;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RomError {
+    Empty,
+    /// The size of the ROM, which is more than `MAX_PROGRAM_SIZE`.
+    TooLarge(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Empty => write!(formatter, "the ROM is empty"),
+            RomError::TooLarge(size) => write!(
+                formatter,
+                "the ROM is {} bytes but at most {} bytes fit in memory",
+                size, MAX_PROGRAM_SIZE
+            ),
+        }
+    }
+}
+
+/// Checks that a ROM fits in memory after the interpreter area.
+pub fn validate(rom: &[u8]) -> Result<(), RomError> {
+    if rom.is_empty() {
+        return Err(RomError::Empty);
+    }
+    if rom.len() > MAX_PROGRAM_SIZE {
+        return Err(RomError::TooLarge(rom.len()));
+    }
+
+    Ok(())
+}
+
+/// The machine a ROM was written for.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+}
+
+impl Platform {
+    /// `.sc8` files are SUPER-CHIP by convention, anything else is plain
+    /// CHIP-8. The instructions are not looked at: data bytes such as `00 FF`
+    /// would pass for SUPER-CHIP ones.
+    pub fn detect(file_name: &str) -> Platform {
+        if file_name.to_ascii_lowercase().ends_with(".sc8") {
+            Platform::SuperChip
+        } else {
+            Platform::Chip8
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+        }
+    }
+
+    /// Plain CHIP-8 ROMs get the modern quirks, which most of them expect.
+    pub fn get_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::modern(),
+            Platform::SuperChip => Quirks::schip(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_rom {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), validate(&[0x00, 0xe0]));
+        assert_eq!(Err(RomError::Empty), validate(&[]));
+        assert_eq!(
+            Err(RomError::TooLarge(MAX_PROGRAM_SIZE + 1)),
+            validate(&vec![0; MAX_PROGRAM_SIZE + 1])
+        );
+    }
+
+    #[test]
+    fn test_detect_from_extension() {
+        assert_eq!(Platform::SuperChip, Platform::detect("CAR.SC8"));
+        assert_eq!(Platform::Chip8, Platform::detect("PONG.ch8"));
+        assert_eq!(Platform::Chip8, Platform::detect("game"));
+    }
+}
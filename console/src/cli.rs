@@ -0,0 +1,303 @@
+use crate::implementation::display::{Theme, THEMES};
+use crate::implementation::graphics::{GraphicsMode, GRAPHICS_MODES};
+use crate::implementation::keyboard::DEFAULT_KEY_HOLD;
+use crate::implementation::renderer::{Renderer, RENDERERS};
+use chip8::keymap::{Keymap, PRESETS};
+use chip8::quirks::{Quirks, PROFILES};
+use chip8::{DEFAULT_INSTRUCTIONS_PER_FRAME, FRAMES_PER_SECOND};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const MAX_SCALE: usize = 4;
+
+pub const USAGE: &str = "\
+Usage: console [OPTIONS] <ROM>
+
+Options:
+    --ips <N>            Instructions executed per second [default: 600]
+    --profile <NAME>     Quirk profile: modern, cosmac or schip [default: modern]
+    --seed <N>           Seed the random number generator
+    --layout <NAME>      Keyboard layout: qwerty, azerty, qwertz or dvorak
+    --key-hold <MS>      How long a key stays held after the terminal last repeated it,
+                         unless it reports releases [default: 250]
+    --theme <NAME>       Colour theme: classic, green, amber or inverted [default: classic]
+    --renderer <NAME>    Pixels per cell: ascii (1), half-block (2) or braille (8) [default: ascii]
+    --graphics <MODE>    Draw a bitmap: off, auto, sixel or kitty [default: off]
+    --scale <N>          Scale pixels up, 1 to 4 [default: 1]
+    --paused             Start paused
+    --status             Show speed, sound and pause state under the screen
+    --debug              Show the debugger panel, forces text output
+    --load-state <FILE>  Resume from a save state file
+    --save-state <FILE>  On quit, write the last state saved with K to a file
+    -h, --help           Print this help
+
+Set CHIP8_KEYMAP to a keymap config file to customise the keys.";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom_path: PathBuf,
+    pub instructions_per_second: usize,
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub keymap: Option<Keymap>,
+    pub key_hold: Duration,
+    pub theme: Theme,
+    pub renderer: Renderer,
+    pub graphics: GraphicsMode,
+    pub scale: usize,
+    pub paused: bool,
+    pub status: bool,
+    pub debug: bool,
+    pub load_state: Option<PathBuf>,
+    pub save_state: Option<PathBuf>,
+}
+
+impl Options {
+    fn new(rom_path: PathBuf) -> Self {
+        Options {
+            rom_path,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_FRAME * FRAMES_PER_SECOND,
+            quirks: Quirks::default(),
+            seed: None,
+            keymap: None,
+            key_hold: DEFAULT_KEY_HOLD,
+            theme: Theme::default(),
+            renderer: Renderer::default(),
+            graphics: GraphicsMode::default(),
+            scale: 1,
+            paused: false,
+            status: false,
+            debug: false,
+            load_state: None,
+            save_state: None,
+        }
+    }
+
+    pub fn get_instructions_per_frame(&self) -> usize {
+        (self.instructions_per_second as f32 / FRAMES_PER_SECOND as f32).round() as usize
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Run(Options),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+/// Parses the arguments following the program name. Options take their value
+/// either as the next argument or after `=`.
+pub fn parse<I>(args: I) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut rom_path = None;
+    let mut options = Options::new(PathBuf::new());
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if rom_path.is_some() {
+                return Err(CliError(format!("unexpected argument `{}`", arg)));
+            }
+            rom_path = Some(PathBuf::from(arg));
+            continue;
+        }
+
+        let (name, inline_value) = match arg.find('=') {
+            Some(index) => (arg[..index].to_owned(), Some(arg[index + 1..].to_owned())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError(format!("`{}` needs a value", name)))
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--ips" => {
+                options.instructions_per_second = parse_number(&name, &value()?)?;
+                if options.get_instructions_per_frame() == 0 {
+                    return Err(CliError(format!(
+                        "`--ips` must be at least {}",
+                        FRAMES_PER_SECOND / 2
+                    )));
+                }
+            }
+            "--profile" => {
+                let profile = value()?;
+                options.quirks = Quirks::from_profile(&profile)
+                    .ok_or_else(|| unknown_choice("profile", &profile, &PROFILES))?;
+            }
+            "--seed" => options.seed = Some(parse_number(&name, &value()?)?),
+            "--layout" => {
+                let layout = value()?;
+                options.keymap = Some(
+                    Keymap::from_preset(&layout)
+                        .ok_or_else(|| unknown_choice("layout", &layout, &PRESETS))?,
+                );
+            }
+            "--key-hold" => {
+                options.key_hold = Duration::from_millis(parse_number(&name, &value()?)?)
+            }
+            "--theme" => {
+                let theme = value()?;
+                options.theme = Theme::from_name(&theme)
+                    .ok_or_else(|| unknown_choice("theme", &theme, &THEMES))?;
+            }
+            "--renderer" => {
+                let renderer = value()?;
+                options.renderer = Renderer::from_name(&renderer)
+                    .ok_or_else(|| unknown_choice("renderer", &renderer, &RENDERERS))?;
+            }
+            "--graphics" => {
+                let graphics = value()?;
+                options.graphics = GraphicsMode::from_name(&graphics)
+                    .ok_or_else(|| unknown_choice("graphics mode", &graphics, &GRAPHICS_MODES))?;
+            }
+            "--scale" => {
+                options.scale = parse_number(&name, &value()?)?;
+                if options.scale == 0 || options.scale > MAX_SCALE {
+                    return Err(CliError(format!(
+                        "`--scale` must be between 1 and {}",
+                        MAX_SCALE
+                    )));
+                }
+            }
+            "--paused" => options.paused = true,
+            "--status" => options.status = true,
+            "--debug" => options.debug = true,
+            "--load-state" => options.load_state = Some(PathBuf::from(value()?)),
+            "--save-state" => options.save_state = Some(PathBuf::from(value()?)),
+            _ => return Err(CliError(format!("unknown option `{}`", name))),
+        }
+    }
+
+    match rom_path {
+        Some(rom_path) => {
+            options.rom_path = rom_path;
+            Ok(Command::Run(options))
+        }
+        None => Err(CliError("missing path to a ROM".to_owned())),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError(format!("`{}` expects a number, got `{}`", name, value)))
+}
+
+fn unknown_choice(kind: &str, value: &str, choices: &[&str]) -> CliError {
+    CliError(format!(
+        "unknown {} `{}`, expected one of {}",
+        kind,
+        value,
+        choices.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, CliError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let command = parse_args(&["pong.ch8"]).unwrap();
+
+        assert_eq!(
+            Command::Run(Options::new(PathBuf::from("pong.ch8"))),
+            command
+        );
+    }
+
+    #[test]
+    fn test_all_options() {
+        let command = parse_args(&[
+            "--ips=900",
+            "--profile",
+            "cosmac",
+            "--seed",
+            "42",
+            "--layout",
+            "azerty",
+            "--key-hold=100",
+            "--theme=amber",
+            "--renderer",
+            "braille",
+            "--graphics",
+            "auto",
+            "--scale",
+            "2",
+            "--paused",
+            "--status",
+            "--debug",
+            "--load-state",
+            "pong.state",
+            "--save-state=pong-after.state",
+            "pong.ch8",
+        ])
+        .unwrap();
+
+        let options = match command {
+            Command::Run(options) => options,
+            Command::Help => panic!("expected options"),
+        };
+        assert_eq!(15, options.get_instructions_per_frame());
+        assert_eq!(Quirks::cosmac(), options.quirks);
+        assert_eq!(Some(42), options.seed);
+        assert_eq!(Some(Keymap::azerty()), options.keymap);
+        assert_eq!(Duration::from_millis(100), options.key_hold);
+        assert_eq!(Theme::Amber, options.theme);
+        assert_eq!(Renderer::Braille, options.renderer);
+        assert_eq!(GraphicsMode::Auto, options.graphics);
+        assert_eq!(2, options.scale);
+        assert!(options.paused);
+        assert!(options.status);
+        assert!(options.debug);
+        assert_eq!(Some(PathBuf::from("pong.state")), options.load_state);
+        assert_eq!(Some(PathBuf::from("pong-after.state")), options.save_state);
+    }
+
+    #[test]
+    fn test_help_wins_over_missing_rom() {
+        assert_eq!(Ok(Command::Help), parse_args(&["--help"]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(CliError("missing path to a ROM".to_owned())),
+            parse_args(&[])
+        );
+        assert_eq!(
+            Err(CliError("`--seed` needs a value".to_owned())),
+            parse_args(&["pong.ch8", "--seed"])
+        );
+        assert_eq!(
+            Err(CliError("`--scale` must be between 1 and 4".to_owned())),
+            parse_args(&["pong.ch8", "--scale", "9"])
+        );
+        assert_eq!(
+            Err(CliError(
+                "unknown theme `pink`, expected one of classic, green, amber, inverted".to_owned()
+            )),
+            parse_args(&["pong.ch8", "--theme", "pink"])
+        );
+    }
+}
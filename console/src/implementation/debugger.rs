@@ -0,0 +1,192 @@
+use chip8::chipset::DebugState;
+use chip8::disassembler::disassemble;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+pub const PANEL_WIDTH: usize = 32;
+pub const PANEL_HEIGHT: usize = 25;
+const INSTRUCTIONS_BEFORE_PC: u16 = 4;
+const INSTRUCTIONS_SHOWN: usize = 9;
+const HEXDUMP_ROWS: usize = 4;
+const HEXDUMP_ROW_LENGTH: usize = 8;
+const REGISTERS_PER_ROW: usize = 4;
+
+/// Lines of the debugger panel drawn next to the screen, `PANEL_HEIGHT` lines
+/// of `PANEL_WIDTH` characters.
+pub fn render_panel<M>(
+    debug_state: &DebugState,
+    paused: bool,
+    breakpoints: &BTreeSet<u16>,
+    read_memory: M,
+) -> Vec<String>
+where
+    M: Fn(u16, usize) -> Vec<u8>,
+{
+    let mut lines = render_state(debug_state, paused, breakpoints, read_memory);
+    lines.push(String::new());
+    lines.push("F5 run/pause F10 step".to_owned());
+    lines.push("F9 breakpoint at PC".to_owned());
+
+    lines
+        .into_iter()
+        .map(|line| format!("{:width$.width$}", line, width = PANEL_WIDTH))
+        .collect()
+}
+
+/// Registers, stack, disassembly around PC and a hexdump at I.
+/// `read_memory` returns up to `length` bytes at `address`.
+pub fn render_state<M>(
+    debug_state: &DebugState,
+    paused: bool,
+    breakpoints: &BTreeSet<u16>,
+    read_memory: M,
+) -> Vec<String>
+where
+    M: Fn(u16, usize) -> Vec<u8>,
+{
+    let mut lines = Vec::new();
+
+    lines.push(format!(
+        "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+        debug_state.program_counter,
+        debug_state.address_register,
+        debug_state.delay_timer,
+        debug_state.sound_timer
+    ));
+    lines.push(
+        if debug_state.waiting_for_key {
+            "WAITING FOR KEY"
+        } else if paused {
+            "PAUSED"
+        } else {
+            "RUNNING"
+        }
+        .to_owned(),
+    );
+
+    for (row, registers) in debug_state.registers.chunks(REGISTERS_PER_ROW).enumerate() {
+        let mut line = String::new();
+        for (index, value) in registers.iter().enumerate() {
+            write!(
+                line,
+                "V{:X} {:02X}  ",
+                row * REGISTERS_PER_ROW + index,
+                value
+            )
+            .unwrap();
+        }
+        lines.push(line);
+    }
+
+    let mut stack = "Stack".to_owned();
+    for frame in debug_state.stack.iter().rev() {
+        write!(stack, " {:03X}", frame).unwrap();
+    }
+    lines.push(stack);
+    lines.push(String::new());
+
+    let start = debug_state
+        .program_counter
+        .saturating_sub(INSTRUCTIONS_BEFORE_PC * 2);
+    let program = read_memory(start, INSTRUCTIONS_SHOWN * 2);
+    for (index, instruction) in program.chunks_exact(2).enumerate() {
+        let address = start + index as u16 * 2;
+        let breakpoint = if breakpoints.contains(&address) {
+            '*'
+        } else {
+            ' '
+        };
+        let current = if address == debug_state.program_counter {
+            '>'
+        } else {
+            ' '
+        };
+        let data = (u16::from(instruction[0]) << 8) | u16::from(instruction[1]);
+
+        lines.push(format!(
+            "{}{}{:03X} {:04X} {}",
+            breakpoint,
+            current,
+            address,
+            data,
+            disassemble(data)
+        ));
+    }
+    lines.push(String::new());
+
+    let start = debug_state.address_register & !(HEXDUMP_ROW_LENGTH as u16 - 1);
+    let memory = read_memory(start, HEXDUMP_ROWS * HEXDUMP_ROW_LENGTH);
+    for (index, row) in memory.chunks(HEXDUMP_ROW_LENGTH).enumerate() {
+        let mut line = format!("{:03X}", start as usize + index * HEXDUMP_ROW_LENGTH);
+        for byte in row {
+            write!(line, " {:02X}", byte).unwrap();
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod test_debugger {
+    use super::*;
+
+    fn create_debug_state() -> DebugState {
+        let mut registers = [0; 16];
+        registers[0xa] = 0x2b;
+
+        DebugState {
+            registers,
+            address_register: 0x20a,
+            program_counter: 0x202,
+            delay_timer: 0x10,
+            sound_timer: 0,
+            stack: vec![0x300, 0x400],
+            waiting_for_key: false,
+        }
+    }
+
+    fn read_memory(address: u16, length: usize) -> Vec<u8> {
+        let program = [0x00, 0xe0, 0x60, 0x01, 0x12, 0x02];
+
+        (address as usize..address as usize + length)
+            .map(|address| match address.checked_sub(0x200) {
+                Some(index) => *program.get(index).unwrap_or(&0),
+                None => 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_panel_lines_have_fixed_width() {
+        let lines = render_panel(&create_debug_state(), false, &BTreeSet::new(), read_memory);
+
+        assert!(lines.iter().all(|line| line.chars().count() == PANEL_WIDTH));
+    }
+
+    #[test]
+    fn test_panel_shows_registers_stack_and_state() {
+        let lines = render_panel(&create_debug_state(), true, &BTreeSet::new(), read_memory);
+
+        assert_eq!("PC 202  I 20A  DT 10  ST 00", lines[0].trim_end());
+        assert_eq!("PAUSED", lines[1].trim_end());
+        assert_eq!("V8 00  V9 00  VA 2B  VB 00", lines[4].trim_end());
+        assert_eq!("Stack 400 300", lines[6].trim_end());
+    }
+
+    #[test]
+    fn test_panel_marks_program_counter_and_breakpoints() {
+        let breakpoints = [0x204].iter().cloned().collect::<BTreeSet<u16>>();
+        let lines = render_panel(&create_debug_state(), false, &breakpoints, read_memory);
+
+        assert_eq!(" >202 6001 LD V0, 0x01", lines[12].trim_end());
+        assert_eq!("* 204 1202 JP 0x202", lines[13].trim_end());
+    }
+
+    #[test]
+    fn test_panel_dumps_memory_at_address_register() {
+        let lines = render_panel(&create_debug_state(), false, &BTreeSet::new(), read_memory);
+
+        assert_eq!("208 00 00 00 00 00 00 00 00", lines[18].trim_end());
+    }
+}
@@ -1,19 +1,373 @@
+use super::frame::TextFrame;
+use super::graphics::{self, Bitmap, GraphicsProtocol, Rgb};
+use super::renderer::Renderer;
 use chip8::display::*;
+use std::cmp;
 use std::io::{stdout, Stdout, Write};
 use std::ops;
+use termion::color;
 use termion::raw::{IntoRawMode, RawTerminal};
 
+pub const THEMES: [&str; 4] = ["classic", "green", "amber", "inverted"];
+/// Size in image pixels of a CHIP-8 pixel at scale 1 when drawing bitmaps.
+const GRAPHICS_PIXEL_SIZE: usize = 4;
+/// Blank columns between the screen and the side panel.
+const PANEL_MARGIN: usize = 2;
+/// The status line may run past a narrow screen up to this width.
+const STATUS_LINE_WIDTH: usize = 48;
+const DEFAULT_PALETTE: (Rgb, Rgb) = ((0xff, 0xff, 0xff), (0, 0, 0));
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Theme {
+    #[default]
+    Classic,
+    Green,
+    Amber,
+    Inverted,
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name.to_ascii_lowercase().as_str() {
+            "classic" => Some(Theme::Classic),
+            "green" => Some(Theme::Green),
+            "amber" => Some(Theme::Amber),
+            "inverted" => Some(Theme::Inverted),
+            _ => None,
+        }
+    }
+
+    /// Lit and unlit pixel colours, or `None` to keep the terminal's.
+    fn get_palette(self) -> Option<(Rgb, Rgb)> {
+        match self {
+            Theme::Classic => None,
+            Theme::Green => Some(((0x33, 0xff, 0x33), (0, 0x1a, 0))),
+            Theme::Amber => Some(((0xff, 0xb0, 0), (0x1a, 0x0f, 0))),
+            Theme::Inverted => Some(((0, 0, 0), (0xff, 0xff, 0xff))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Output {
+    Text(Renderer),
+    Graphics(GraphicsProtocol),
+}
+
+/// Where the screen is drawn, in cells from the top left of the terminal.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Layout {
+    scale: usize,
+    column: usize,
+    row: usize,
+}
+
+/// Centres the screen, with the side panel next to it and `status_rows` under
+/// it, at the largest scale up to `max_scale` that fits the terminal. `None`
+/// if even scale 1 does not fit.
+fn compute_layout(
+    screen_cells: impl Fn(usize) -> (usize, usize),
+    max_scale: usize,
+    side_panel_size: (usize, usize),
+    status_rows: usize,
+    terminal_size: (usize, usize),
+) -> Option<Layout> {
+    let (panel_width, panel_height) = side_panel_size;
+    let panel_columns = if panel_width > 0 {
+        PANEL_MARGIN + panel_width
+    } else {
+        0
+    };
+
+    (1..=max_scale).rev().find_map(|scale| {
+        let (columns, rows) = screen_cells(scale);
+        let needed_columns = columns + panel_columns;
+        let needed_rows = (rows + status_rows).max(panel_height);
+
+        if needed_columns > terminal_size.0 || needed_rows > terminal_size.1 {
+            return None;
+        }
+
+        Some(Layout {
+            scale,
+            column: (terminal_size.0 - needed_columns) / 2,
+            row: (terminal_size.1 - needed_rows) / 2,
+        })
+    })
+}
+
 pub struct ConsoleDisplay {
     terminal: RawTerminal<Stdout>,
+    output: Output,
+    palette: Option<(Rgb, Rgb)>,
+    colours: String,
+    frame: TextFrame,
+    side_panel: Option<(usize, usize, TextFrame)>,
+    status_line: Option<TextFrame>,
+    max_scale: usize,
+    layout: Layout,
+    /// Size needed at scale 1, set while the terminal is smaller.
+    too_small: Option<(usize, usize)>,
+    width: usize,
+    height: usize,
 }
 
 impl ConsoleDisplay {
-    pub fn new() -> Self {
+    /// Every CHIP-8 pixel is scaled up `scale` times before the renderer packs
+    /// pixels into terminal cells or the bitmap is encoded. Text is scaled
+    /// down again when the terminal is too small for it.
+    pub fn new(output: Output, theme: Theme, scale: usize) -> Self {
+        ConsoleDisplay::with_resolution(output, theme, scale, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+
+    pub fn with_resolution(
+        output: Output,
+        theme: Theme,
+        scale: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
         let mut terminal = stdout().into_raw_mode().unwrap();
         write!(terminal, "{}{}", termion::cursor::Hide, termion::clear::All).unwrap();
         terminal.flush().unwrap();
 
-        ConsoleDisplay { terminal }
+        let palette = theme.get_palette();
+        let colours = match palette {
+            Some(((on_red, on_green, on_blue), (off_red, off_green, off_blue))) => format!(
+                "{}{}",
+                color::Fg(color::Rgb(on_red, on_green, on_blue)),
+                color::Bg(color::Rgb(off_red, off_green, off_blue))
+            ),
+            None => String::new(),
+        };
+
+        let mut display = ConsoleDisplay {
+            terminal,
+            output,
+            palette,
+            colours,
+            frame: TextFrame::new(0, 0),
+            side_panel: None,
+            status_line: None,
+            max_scale: scale,
+            layout: Layout {
+                scale,
+                column: 0,
+                row: 0,
+            },
+            too_small: None,
+            width,
+            height,
+        };
+        display.fit_terminal();
+
+        display
+    }
+
+    /// Makes room for a side panel of `width` x `height` cells.
+    pub fn reserve_side_panel(&mut self, width: usize, height: usize) {
+        self.side_panel = Some((width, height, TextFrame::new(width, height)));
+        self.fit_terminal();
+    }
+
+    /// Makes room for a line of text under the screen.
+    pub fn reserve_status_line(&mut self) {
+        self.status_line = Some(TextFrame::new(0, 1));
+        self.fit_terminal();
+    }
+
+    /// Lays the screen out again for the current terminal size and clears it.
+    /// The emulator has to redraw afterwards.
+    pub fn fit_terminal(&mut self) {
+        let terminal_size = termion::terminal_size()
+            .map(|(columns, rows)| (columns as usize, rows as usize))
+            .ok();
+
+        if let Output::Text(renderer) = self.output {
+            let (width, height) = (self.width, self.height);
+            let screen_cells =
+                |scale: usize| renderer.get_cell_count(width * scale, height * scale);
+            let side_panel_size = self
+                .side_panel
+                .as_ref()
+                .map_or((0, 0), |(width, height, _)| (*width, *height));
+
+            let status_rows = self.status_line.is_some() as usize;
+
+            // Without a size, as when not writing to a terminal, the screen
+            // goes to the top left at full scale.
+            let layout = match terminal_size {
+                Some(terminal_size) => compute_layout(
+                    screen_cells,
+                    self.max_scale,
+                    side_panel_size,
+                    status_rows,
+                    terminal_size,
+                ),
+                None => Some(Layout {
+                    scale: self.max_scale,
+                    column: 0,
+                    row: 0,
+                }),
+            };
+
+            match layout {
+                Some(layout) => {
+                    let (columns, rows) = screen_cells(layout.scale);
+                    self.frame = TextFrame::new(columns, rows);
+                    if self.status_line.is_some() {
+                        let available_columns = terminal_size
+                            .map_or(STATUS_LINE_WIDTH, |(columns, _)| columns - layout.column);
+                        let width =
+                            cmp::min(cmp::max(columns, STATUS_LINE_WIDTH), available_columns);
+                        self.status_line = Some(TextFrame::new(width, 1));
+                    }
+                    self.layout = layout;
+                    self.too_small = None;
+                }
+                None => {
+                    let (columns, rows) = screen_cells(1);
+                    let panel_columns = match side_panel_size.0 {
+                        0 => 0,
+                        width => PANEL_MARGIN + width,
+                    };
+                    self.too_small = Some((
+                        columns + panel_columns,
+                        (rows + status_rows).max(side_panel_size.1),
+                    ));
+                }
+            }
+        }
+
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.frame.invalidate();
+        if let Some((_, _, side_panel)) = &mut self.side_panel {
+            side_panel.invalidate();
+        }
+        if let Some(status_line) = &mut self.status_line {
+            status_line.invalidate();
+        }
+        write!(self.terminal, "{}{}", self.colours, termion::clear::All).unwrap();
+
+        if let Some((columns, rows)) = self.too_small {
+            write!(
+                self.terminal,
+                "{}Terminal too small, needs {}x{}",
+                termion::cursor::Goto(1, 1),
+                columns,
+                rows
+            )
+            .unwrap();
+        }
+        self.terminal.flush().unwrap();
+    }
+}
+
+impl ConsoleDisplay {
+    /// Bitmaps replace the whole picture, so any change redraws everything.
+    fn draw_bitmap<P>(&mut self, protocol: GraphicsProtocol, is_lit: P)
+    where
+        P: Fn(usize, usize) -> bool,
+    {
+        let (on, off) = self.palette.unwrap_or(DEFAULT_PALETTE);
+        let bitmap = Bitmap::new(
+            self.width * self.layout.scale * GRAPHICS_PIXEL_SIZE,
+            self.height * self.layout.scale * GRAPHICS_PIXEL_SIZE,
+            |x, y| is_lit(x / GRAPHICS_PIXEL_SIZE, y / GRAPHICS_PIXEL_SIZE),
+        );
+
+        // The layout of bitmaps is the top left corner, their size in cells
+        // being unknown.
+        write!(
+            self.terminal,
+            "{}{}",
+            self.goto(0, 0),
+            graphics::encode(protocol, &bitmap, on, off)
+        )
+        .unwrap();
+        self.terminal.flush().unwrap();
+    }
+
+    fn goto(&self, column: usize, row: usize) -> String {
+        termion::cursor::Goto(
+            (self.layout.column + column + 1) as u16,
+            (self.layout.row + row + 1) as u16,
+        )
+        .to_string()
+    }
+}
+
+impl ConsoleDisplay {
+    /// Draws `lines` into the panel reserved with `reserve_side_panel`, to the
+    /// right of the text screen in the terminal's own colours. Nothing is drawn
+    /// next to bitmaps as their width in cells is unknown.
+    pub fn draw_side_panel(&mut self, lines: &[String]) {
+        if self.too_small.is_some() {
+            return;
+        }
+        let screen_columns = match self.output {
+            Output::Text(_) => self.frame.get_width(),
+            Output::Graphics(_) => return,
+        };
+
+        let mut output = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+        let reset_length = output.len();
+        let mut runs = Vec::new();
+        if let Some((width, height, frame)) = &mut self.side_panel {
+            for (row, line) in lines.iter().take(*height).enumerate() {
+                let characters = line.chars().take(*width).collect::<Vec<char>>();
+                for (column, text) in frame.update_row(row, 0, &characters) {
+                    runs.push((screen_columns + PANEL_MARGIN + column, row, text));
+                }
+            }
+        }
+
+        for (column, row, text) in runs {
+            output.push_str(&self.goto(column, row));
+            output.push_str(&text);
+        }
+
+        if output.len() > reset_length {
+            self.terminal.write_all(output.as_bytes()).unwrap();
+            self.terminal.flush().unwrap();
+        }
+    }
+}
+
+impl ConsoleDisplay {
+    /// Draws `text` on the line reserved with `reserve_status_line`, under the
+    /// text screen. Bitmaps have no status line as their height in cells is
+    /// unknown.
+    pub fn draw_status_line(&mut self, text: &str) {
+        if self.too_small.is_some() || matches!(self.output, Output::Graphics(_)) {
+            return;
+        }
+        let row = self.frame.get_height();
+
+        let mut runs = Vec::new();
+        if let Some(status_line) = &mut self.status_line {
+            let width = status_line.get_width();
+            let characters = text
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(width)
+                .collect::<Vec<char>>();
+            runs = status_line.update_row(0, 0, &characters);
+        }
+
+        if runs.is_empty() {
+            return;
+        }
+        let mut output = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+        for (column, text) in runs {
+            output.push_str(&self.goto(column, row));
+            output.push_str(&text);
+        }
+        self.terminal.write_all(output.as_bytes()).unwrap();
+        self.terminal.flush().unwrap();
     }
 }
 
@@ -21,7 +375,9 @@ impl Drop for ConsoleDisplay {
     fn drop(&mut self) {
         write!(
             self.terminal,
-            "{}{}",
+            "{}{}{}{}",
+            color::Fg(color::Reset),
+            color::Bg(color::Reset),
             termion::clear::All,
             termion::cursor::Show
         )
@@ -31,22 +387,140 @@ impl Drop for ConsoleDisplay {
 }
 
 impl GraphicDisplay for ConsoleDisplay {
-    fn draw<M>(&mut self, memory: &M)
+    fn draw<M>(&mut self, memory: &M, dirty_regions: &[DirtyRegion])
     where
         M: ops::Index<usize, Output = [u8]>,
     {
-        for y in 0..DISPLAY_HEIGHT {
-            for x in 0..DISPLAY_WIDTH {
-                let character = if memory[y][x] == 1 { '*' } else { ' ' };
-                write!(
-                    self.terminal,
-                    "{}{}",
-                    termion::cursor::Goto((x + 1) as u16, (y + 1) as u16),
-                    character
-                )
-                .unwrap();
+        if self.too_small.is_some() {
+            return;
+        }
+        let (scale, width, height) = (self.layout.scale, self.width, self.height);
+        let is_lit = |x: usize, y: usize| {
+            x < width * scale && y < height * scale && memory[y / scale][x / scale] == 1
+        };
+
+        let renderer = match self.output {
+            Output::Text(renderer) => renderer,
+            Output::Graphics(protocol) => {
+                if !dirty_regions.is_empty() {
+                    self.draw_bitmap(protocol, is_lit);
+                }
+                return;
+            }
+        };
+
+        // Only cells that differ from the last frame are sent, in one write.
+        let mut output = self.colours.clone();
+        let mut runs = Vec::new();
+        for region in dirty_regions {
+            let scaled_region = DirtyRegion::new(
+                region.x * scale,
+                region.y * scale,
+                region.width * scale,
+                region.height * scale,
+            );
+            let cells = renderer.get_cell_region(&scaled_region);
+
+            for row in cells.y..cells.y + cells.height {
+                let characters = (cells.x..cells.x + cells.width)
+                    .map(|column| renderer.render_cell(is_lit, column, row))
+                    .collect::<Vec<char>>();
+
+                for (column, text) in self.frame.update_row(row, cells.x, &characters) {
+                    runs.push((column, row, text));
+                }
             }
         }
-        self.terminal.flush().unwrap();
+
+        for (column, row, text) in runs {
+            output.push_str(&self.goto(column, row));
+            output.push_str(&text);
+        }
+
+        if output.len() > self.colours.len() {
+            self.terminal.write_all(output.as_bytes()).unwrap();
+            self.terminal.flush().unwrap();
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_display {
+    use super::*;
+
+    fn screen_cells(scale: usize) -> (usize, usize) {
+        (64 * scale, 32 * scale)
+    }
+
+    #[test]
+    fn test_layout_centres_largest_fitting_scale() {
+        assert_eq!(
+            Some(Layout {
+                scale: 2,
+                column: 8,
+                row: 1
+            }),
+            compute_layout(screen_cells, 4, (0, 0), 0, (144, 66))
+        );
+    }
+
+    #[test]
+    fn test_layout_makes_room_for_side_panel() {
+        assert_eq!(
+            Some(Layout {
+                scale: 1,
+                column: 2,
+                row: 0
+            }),
+            compute_layout(screen_cells, 2, (30, 40), 1, (100, 40))
+        );
+    }
+
+    #[test]
+    fn test_layout_fails_on_small_terminal() {
+        assert_eq!(None, compute_layout(screen_cells, 1, (0, 0), 0, (80, 24)));
+    }
+
+    #[test]
+    fn test_layout_makes_room_for_status_line() {
+        assert_eq!(
+            Some(Layout {
+                scale: 1,
+                column: 8,
+                row: 0
+            }),
+            compute_layout(screen_cells, 2, (0, 0), 1, (80, 33))
+        );
+        assert_eq!(None, compute_layout(screen_cells, 1, (0, 0), 1, (80, 32)));
+    }
+
+    #[test]
+    fn test_layout_at_high_resolution() {
+        let half_block_cells =
+            |scale: usize| Renderer::HalfBlock.get_cell_count(128 * scale, 64 * scale);
+        assert_eq!(
+            Some(Layout {
+                scale: 1,
+                column: 8,
+                row: 4
+            }),
+            compute_layout(half_block_cells, 2, (0, 0), 0, (144, 40))
+        );
+
+        let braille_cells =
+            |scale: usize| Renderer::Braille.get_cell_count(128 * scale, 64 * scale);
+        assert_eq!(
+            Some(Layout {
+                scale: 1,
+                column: 8,
+                row: 4
+            }),
+            compute_layout(braille_cells, 2, (0, 0), 0, (80, 24))
+        );
+        assert_eq!(None, compute_layout(braille_cells, 1, (0, 0), 0, (60, 24)));
     }
 }
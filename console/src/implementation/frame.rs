@@ -0,0 +1,116 @@
+/// Unchanged cells between two changed ones are rewritten rather than
+/// jumped over when that is shorter than a cursor move.
+const MAX_BRIDGED_GAP: usize = 3;
+
+/// The characters currently on the terminal, used to only send what changed.
+pub struct TextFrame {
+    width: usize,
+    cells: Vec<Option<char>>,
+}
+
+impl TextFrame {
+    pub fn new(width: usize, height: usize) -> Self {
+        TextFrame {
+            width,
+            cells: vec![None; width * height],
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.cells.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    /// Forgets the frame so that every cell counts as changed next time.
+    pub fn invalidate(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = None;
+        }
+    }
+
+    /// Records `characters` written to `row` from `start` and returns the
+    /// runs that differ from the previous frame as `(column, text)`.
+    pub fn update_row(
+        &mut self,
+        row: usize,
+        start: usize,
+        characters: &[char],
+    ) -> Vec<(usize, String)> {
+        let offset = row * self.width + start;
+        let previous = &mut self.cells[offset..offset + characters.len()];
+        let mut runs: Vec<(usize, String)> = Vec::new();
+        let mut last_changed = None;
+
+        for (index, character) in characters.iter().enumerate() {
+            if previous[index] == Some(*character) {
+                continue;
+            }
+            previous[index] = Some(*character);
+
+            match (last_changed, runs.last_mut()) {
+                (Some(last), Some((_, text))) if index - last <= MAX_BRIDGED_GAP + 1 => {
+                    text.extend(&characters[last + 1..=index]);
+                }
+                _ => runs.push((start + index, character.to_string())),
+            }
+            last_changed = Some(index);
+        }
+
+        runs
+    }
+}
+
+#[cfg(test)]
+mod test_frame {
+    use super::TextFrame;
+
+    #[test]
+    fn test_first_update_writes_everything() {
+        let mut frame = TextFrame::new(4, 1);
+
+        assert_eq!(
+            vec![(0, "ab c".to_owned())],
+            frame.update_row(0, 0, &['a', 'b', ' ', 'c'])
+        );
+    }
+
+    #[test]
+    fn test_unchanged_cells_are_skipped() {
+        let mut frame = TextFrame::new(4, 2);
+        frame.update_row(1, 0, &['a', 'b', 'c', 'd']);
+
+        assert!(frame.update_row(1, 0, &['a', 'b', 'c', 'd']).is_empty());
+        assert_eq!(vec![(2, "x".to_owned())], frame.update_row(1, 2, &['x']));
+    }
+
+    #[test]
+    fn test_short_gaps_are_bridged_and_long_ones_are_not() {
+        let mut frame = TextFrame::new(12, 1);
+        frame.update_row(0, 0, &[' '; 12]);
+
+        let mut characters = [' '; 12];
+        characters[0] = 'a';
+        characters[3] = 'b';
+        characters[11] = 'c';
+
+        assert_eq!(
+            vec![(0, "a  b".to_owned()), (11, "c".to_owned())],
+            frame.update_row(0, 0, &characters)
+        );
+    }
+
+    #[test]
+    fn test_invalidate_forces_full_redraw() {
+        let mut frame = TextFrame::new(2, 1);
+        frame.update_row(0, 0, &['a', 'b']);
+        frame.invalidate();
+
+        assert_eq!(
+            vec![(0, "ab".to_owned())],
+            frame.update_row(0, 0, &['a', 'b'])
+        );
+    }
+}
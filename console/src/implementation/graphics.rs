@@ -0,0 +1,292 @@
+use std::env;
+use std::fmt::Write;
+
+pub const GRAPHICS_MODES: [&str; 4] = ["off", "auto", "sixel", "kitty"];
+/// Device attributes request, answered with `ESC [ ? <attributes> c`.
+pub const DEVICE_ATTRIBUTES_QUERY: &str = "\x1b[c";
+
+const SIXEL_BAND_HEIGHT: usize = 6;
+const SIXEL_OFFSET: u8 = 0x3f;
+const SIXEL_ATTRIBUTE: &str = "4";
+const KITTY_CHUNK_SIZE: usize = 4096;
+const KITTY_IMAGE_ID: u32 = 1;
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub type Rgb = (u8, u8, u8);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum GraphicsProtocol {
+    Sixel,
+    Kitty,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum GraphicsMode {
+    #[default]
+    Off,
+    Auto,
+    Forced(GraphicsProtocol),
+}
+
+impl GraphicsMode {
+    pub fn from_name(name: &str) -> Option<GraphicsMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(GraphicsMode::Off),
+            "auto" => Some(GraphicsMode::Auto),
+            "sixel" => Some(GraphicsMode::Forced(GraphicsProtocol::Sixel)),
+            "kitty" => Some(GraphicsMode::Forced(GraphicsProtocol::Kitty)),
+            _ => None,
+        }
+    }
+}
+
+/// A two-colour image, already scaled to its size on screen.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new<P>(width: usize, height: usize, is_lit: P) -> Self
+    where
+        P: Fn(usize, usize) -> bool,
+    {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(is_lit(x, y));
+            }
+        }
+
+        Bitmap {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn is_lit(&self, x: usize, y: usize) -> bool {
+        y < self.height && self.pixels[y * self.width + x]
+    }
+}
+
+/// Kitty advertises itself through the environment, as do terminals that
+/// borrowed its protocol.
+pub fn detect_kitty_from_environment() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+    env::var_os("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term_program == "WezTerm"
+        || term_program == "ghostty"
+}
+
+/// Whether a reply to `DEVICE_ATTRIBUTES_QUERY` lists sixel graphics.
+pub fn supports_sixel(response: &[u8]) -> bool {
+    let response = String::from_utf8_lossy(response);
+    let attributes = match (response.find("\x1b[?"), response.rfind('c')) {
+        (Some(start), Some(end)) if start + 3 <= end => &response[start + 3..end],
+        _ => return false,
+    };
+
+    attributes
+        .split(';')
+        .any(|attribute| attribute == SIXEL_ATTRIBUTE)
+}
+
+pub fn encode(protocol: GraphicsProtocol, bitmap: &Bitmap, on: Rgb, off: Rgb) -> String {
+    match protocol {
+        GraphicsProtocol::Sixel => encode_sixel(bitmap, on, off),
+        GraphicsProtocol::Kitty => encode_kitty(bitmap, on, off),
+    }
+}
+
+/// Sixel image with colour register 0 for unlit and 1 for lit pixels. Every
+/// band of six rows is painted once per colour.
+pub fn encode_sixel(bitmap: &Bitmap, on: Rgb, off: Rgb) -> String {
+    let mut output = String::new();
+    write!(output, "\x1bPq\"1;1;{};{}", bitmap.width, bitmap.height).unwrap();
+    for (register, colour) in [off, on].iter().enumerate() {
+        write!(
+            output,
+            "#{};2;{};{};{}",
+            register,
+            to_percent(colour.0),
+            to_percent(colour.1),
+            to_percent(colour.2)
+        )
+        .unwrap();
+    }
+
+    for band in 0..bitmap.height.div_ceil(SIXEL_BAND_HEIGHT) {
+        for (register, lit) in [false, true].iter().enumerate() {
+            if register > 0 {
+                output.push('$');
+            }
+            write!(output, "#{}", register).unwrap();
+
+            let sixels = (0..bitmap.width)
+                .map(|x| {
+                    let mut sixel = 0;
+                    for row in 0..SIXEL_BAND_HEIGHT {
+                        let y = band * SIXEL_BAND_HEIGHT + row;
+                        if y < bitmap.height && bitmap.is_lit(x, y) == *lit {
+                            sixel |= 1 << row;
+                        }
+                    }
+
+                    (SIXEL_OFFSET + sixel) as char
+                })
+                .collect::<Vec<char>>();
+            push_run_length_encoded(&mut output, &sixels);
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\");
+
+    output
+}
+
+/// Kitty graphics protocol image as 24-bit RGB, replacing the previous frame
+/// as both share the same image id.
+pub fn encode_kitty(bitmap: &Bitmap, on: Rgb, off: Rgb) -> String {
+    let mut rgb = Vec::with_capacity(bitmap.pixels.len() * 3);
+    for lit in &bitmap.pixels {
+        let colour = if *lit { on } else { off };
+        rgb.extend_from_slice(&[colour.0, colour.1, colour.2]);
+    }
+
+    let payload = encode_base64(&rgb);
+    let chunks = payload
+        .as_bytes()
+        .chunks(KITTY_CHUNK_SIZE)
+        .collect::<Vec<_>>();
+    let mut output = String::new();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = (index + 1 < chunks.len()) as u8;
+        if index == 0 {
+            write!(
+                output,
+                "\x1b_Ga=T,f=24,q=2,C=1,i={},s={},v={},m={};",
+                KITTY_IMAGE_ID, bitmap.width, bitmap.height, more
+            )
+            .unwrap();
+        } else {
+            write!(output, "\x1b_Gm={};", more).unwrap();
+        }
+        output.push_str(std::str::from_utf8(chunk).unwrap());
+        output.push_str("\x1b\\");
+    }
+
+    output
+}
+
+fn push_run_length_encoded(output: &mut String, sixels: &[char]) {
+    let mut index = 0;
+    while index < sixels.len() {
+        let sixel = sixels[index];
+        let run = sixels[index..]
+            .iter()
+            .take_while(|other| **other == sixel)
+            .count();
+
+        if run > 3 {
+            write!(output, "!{}{}", run, sixel).unwrap();
+        } else {
+            output.extend(std::iter::repeat_n(sixel, run));
+        }
+        index += run;
+    }
+}
+
+fn to_percent(channel: u8) -> u32 {
+    (u32::from(channel) * 100 + 127) / 255
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = (u32::from(chunk[0]) << 16)
+            | (u32::from(*chunk.get(1).unwrap_or(&0)) << 8)
+            | u32::from(*chunk.get(2).unwrap_or(&0));
+
+        for position in 0..4 {
+            if position <= chunk.len() {
+                let index = (group >> (18 - position * 6)) & 0x3f;
+                output.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test_graphics {
+    use super::*;
+
+    const WHITE: Rgb = (0xff, 0xff, 0xff);
+    const BLACK: Rgb = (0, 0, 0);
+
+    #[test]
+    fn test_sixel_encoding() {
+        // A 2x2 image with only the top left pixel lit.
+        let bitmap = Bitmap::new(2, 2, |x, y| x == 0 && y == 0);
+
+        assert_eq!(
+            "\x1bPq\"1;1;2;2#0;2;0;0;0#1;2;100;100;100#0AB$#1@?-\x1b\\",
+            encode_sixel(&bitmap, WHITE, BLACK)
+        );
+    }
+
+    #[test]
+    fn test_sixel_run_length_encoding() {
+        let bitmap = Bitmap::new(8, 6, |_, _| true);
+
+        assert_eq!(
+            "\x1bPq\"1;1;8;6#0;2;0;0;0#1;2;100;100;100#0!8?$#1!8~-\x1b\\",
+            encode_sixel(&bitmap, WHITE, BLACK)
+        );
+    }
+
+    #[test]
+    fn test_kitty_encoding() {
+        let bitmap = Bitmap::new(2, 1, |x, _| x == 1);
+
+        assert_eq!(
+            "\x1b_Ga=T,f=24,q=2,C=1,i=1,s=2,v=1,m=0;AAAA////\x1b\\",
+            encode_kitty(&bitmap, WHITE, BLACK)
+        );
+    }
+
+    #[test]
+    fn test_kitty_encoding_splits_large_payloads() {
+        let bitmap = Bitmap::new(64, 64, |_, _| false);
+        let output = encode_kitty(&bitmap, WHITE, BLACK);
+
+        assert!(output.contains(",m=1;"));
+        assert!(output.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn test_base64_padding() {
+        assert_eq!("TWFu", encode_base64(b"Man"));
+        assert_eq!("TWE=", encode_base64(b"Ma"));
+        assert_eq!("TQ==", encode_base64(b"M"));
+    }
+
+    #[test]
+    fn test_device_attributes() {
+        assert!(supports_sixel(b"\x1b[?62;4;6;22c"));
+        assert!(!supports_sixel(b"\x1b[?62;6;22c"));
+        assert!(!supports_sixel(b""));
+    }
+}
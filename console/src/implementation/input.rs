@@ -0,0 +1,258 @@
+const ESCAPE: u8 = 0x1b;
+const KITTY_RELEASE_EVENT: &str = "3";
+const KITTY_SHIFT_MODIFIER: u8 = 0x1;
+const KITTY_CONTROL_MODIFIER: u8 = 0x4;
+
+/// Asks for the kitty keyboard protocol flags, followed by a device attributes
+/// request that every terminal answers so that the reply ends with `c`.
+pub const KITTY_KEYBOARD_QUERY: &str = "\x1b[?u\x1b[c";
+/// Disambiguates escape codes (1), reports release events (2), adds the
+/// shifted character to the key code (4) and sends every key as an escape
+/// code (8) so that plain keys are released too.
+pub const KITTY_KEYBOARD_ENABLE: &str = "\x1b[>15u";
+pub const KITTY_KEYBOARD_DISABLE: &str = "\x1b[<u";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Input {
+    /// A key press, or the terminal repeating it while held.
+    Character(char),
+    /// Only reported with the kitty keyboard protocol.
+    KeyRelease(char),
+    /// F1 to F12. Releases are dropped.
+    FunctionKey(u8),
+}
+
+/// Whether a reply to `KITTY_KEYBOARD_QUERY` includes the flags, which only
+/// terminals supporting the protocol send.
+pub fn supports_kitty_keyboard(response: &[u8]) -> bool {
+    let response = String::from_utf8_lossy(response);
+
+    response.split("\x1b[?").skip(1).any(|reply| {
+        let flags_length = reply.chars().take_while(char::is_ascii_digit).count();
+        flags_length > 0 && reply[flags_length..].starts_with('u')
+    })
+}
+
+/// Splits terminal input into characters and the escape sequences sent for
+/// function keys. Other sequences, such as arrow keys, are dropped and an
+/// escape byte that does not start a sequence is the Esc key. Returns the
+/// inputs and how many bytes were used, leaving an unfinished sequence at
+/// the end of `bytes` for the next read.
+pub fn parse_input(bytes: &[u8]) -> (Vec<Input>, usize) {
+    let mut inputs = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != ESCAPE {
+            let end = bytes[index..]
+                .iter()
+                .position(|byte| *byte == ESCAPE)
+                .map_or(bytes.len(), |position| index + position);
+            inputs.extend(
+                String::from_utf8_lossy(&bytes[index..end])
+                    .chars()
+                    .map(Input::Character),
+            );
+            index = end;
+            continue;
+        }
+
+        match parse_escape_sequence(&bytes[index..]) {
+            Sequence::Complete(length, input) => {
+                inputs.extend(input);
+                index += length;
+            }
+            Sequence::Incomplete => break,
+        }
+    }
+
+    (inputs, index)
+}
+
+enum Sequence {
+    Complete(usize, Option<Input>),
+    Incomplete,
+}
+
+fn parse_escape_sequence(bytes: &[u8]) -> Sequence {
+    match bytes.get(1) {
+        Some(b'[') => {
+            // Parameter and intermediate bytes run up to the final byte.
+            match bytes[2..]
+                .iter()
+                .position(|byte| (0x40..=0x7e).contains(byte))
+            {
+                Some(position) => {
+                    let length = position + 3;
+                    let parameters = String::from_utf8_lossy(&bytes[2..length - 1]);
+                    let input = match bytes[length - 1] {
+                        b'~' => parse_tilde_key(&parameters),
+                        b'u' => parse_kitty_key(&parameters),
+                        // F1 to F4 as sent with modifiers or by the kitty protocol.
+                        final_byte @ b'P'..=b'S' if !is_release(&parameters) => {
+                            Some(Input::FunctionKey(final_byte - b'P' + 1))
+                        }
+                        _ => None,
+                    };
+                    Sequence::Complete(length, input)
+                }
+                None => Sequence::Incomplete,
+            }
+        }
+        Some(b'O') => match bytes.get(2) {
+            Some(final_byte @ b'P'..=b'S') => {
+                Sequence::Complete(3, Some(Input::FunctionKey(final_byte - b'P' + 1)))
+            }
+            Some(_) => Sequence::Complete(3, None),
+            None => Sequence::Incomplete,
+        },
+        _ => Sequence::Complete(1, Some(Input::Character(ESCAPE as char))),
+    }
+}
+
+/// `ESC [ <code> ~` as sent by xterm for F5 to F12, and by the Linux console
+/// for F1 to F4 too. Modifiers after `;` are ignored.
+fn parse_tilde_key(parameters: &str) -> Option<Input> {
+    if is_release(parameters) {
+        return None;
+    }
+    let code = parameters.split(';').next()?.parse::<u8>().ok()?;
+
+    let function_key = match code {
+        11..=15 => code - 10,
+        17..=21 => code - 11,
+        23 | 24 => code - 12,
+        _ => return None,
+    };
+
+    Some(Input::FunctionKey(function_key))
+}
+
+/// `ESC [ <code>[:<shifted>[:<base>]] ; <modifiers>[:<event>] u` from the
+/// kitty keyboard protocol, `code` being the unshifted character. With shift
+/// held the shifted one is used, so that `+` is not read as `=`. Control with
+/// a letter gives the control character, as terminals send without the
+/// protocol.
+fn parse_kitty_key(parameters: &str) -> Option<Input> {
+    let mut fields = parameters.split(';');
+    let mut codes = fields.next()?.split(':');
+    let code = codes.next()?.parse::<u32>().ok()?;
+    let shifted_code = codes.next().and_then(|code| code.parse::<u32>().ok());
+    let modifiers = fields
+        .next()
+        .and_then(|field| field.split(':').next())
+        .and_then(|modifiers| modifiers.parse::<u8>().ok())
+        .map_or(0, |modifiers| modifiers.saturating_sub(1));
+
+    let code = match shifted_code {
+        Some(shifted_code) if modifiers & KITTY_SHIFT_MODIFIER != 0 => shifted_code,
+        _ => code,
+    };
+    let mut character = std::char::from_u32(code)?;
+    if modifiers & KITTY_CONTROL_MODIFIER != 0 && character.is_ascii_alphabetic() {
+        character = (character.to_ascii_lowercase() as u8 & 0x1f) as char;
+    }
+
+    if is_release(parameters) {
+        Some(Input::KeyRelease(character))
+    } else {
+        Some(Input::Character(character))
+    }
+}
+
+/// The kitty protocol appends the event type to the modifiers as `:3` for a
+/// release.
+fn is_release(parameters: &str) -> bool {
+    parameters
+        .split(';')
+        .nth(1)
+        .and_then(|modifiers| modifiers.split(':').nth(1))
+        == Some(KITTY_RELEASE_EVENT)
+}
+
+#[cfg(test)]
+mod test_input {
+    use super::*;
+
+    #[test]
+    fn test_characters_and_lone_escape() {
+        assert_eq!(
+            (
+                vec![
+                    Input::Character('a'),
+                    Input::Character('\u{1b}'),
+                    Input::Character('é')
+                ],
+                4
+            ),
+            parse_input("a\u{1b}é".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_function_keys() {
+        assert_eq!(
+            (
+                vec![
+                    Input::FunctionKey(1),
+                    Input::FunctionKey(5),
+                    Input::FunctionKey(10),
+                    Input::FunctionKey(12)
+                ],
+                20
+            ),
+            parse_input(b"\x1bOP\x1b[15~\x1b[21~\x1b[24;2~")
+        );
+    }
+
+    #[test]
+    fn test_other_sequences_are_dropped() {
+        assert_eq!((vec![Input::Character('x')], 4), parse_input(b"\x1b[Ax"));
+    }
+
+    #[test]
+    fn test_kitty_keys() {
+        assert_eq!(
+            (
+                vec![
+                    Input::Character('a'),
+                    Input::Character('a'),
+                    Input::KeyRelease('a'),
+                    Input::Character('\u{1b}'),
+                    Input::Character('\u{c}'),
+                    Input::FunctionKey(5)
+                ],
+                50
+            ),
+            parse_input(b"\x1b[97u\x1b[97;1:2u\x1b[97;1:3u\x1b[27u\x1b[108;5u\x1b[15~\x1b[15;1:3~")
+        );
+    }
+
+    #[test]
+    fn test_kitty_shifted_keys() {
+        assert_eq!(
+            (
+                vec![
+                    Input::Character('+'),
+                    Input::KeyRelease('+'),
+                    Input::Character('='),
+                    Input::Character('A')
+                ],
+                37
+            ),
+            parse_input(b"\x1b[61:43;2u\x1b[61:43;2:3u\x1b[61u\x1b[97:65;2u")
+        );
+    }
+
+    #[test]
+    fn test_kitty_keyboard_support() {
+        assert!(supports_kitty_keyboard(b"\x1b[?0u\x1b[?62;22c"));
+        assert!(!supports_kitty_keyboard(b"\x1b[?62;22c"));
+        assert!(!supports_kitty_keyboard(b""));
+    }
+
+    #[test]
+    fn test_unfinished_sequence_is_kept() {
+        assert_eq!((vec![Input::Character('q')], 1), parse_input(b"q\x1b[2"));
+    }
+}
@@ -1,74 +1,290 @@
-use chip8::keyboard::{Key, Keyboard};
+use super::input::{self, Input};
+use chip8::host_event::HostEvent;
+use chip8::keyboard::{Key, Keyboard, KEYS_COUNT};
+use chip8::keymap::{Binding, Keymap};
 use std::cell::RefCell;
-use std::io::Read;
+use std::collections::VecDeque;
+use std::io::{stdout, Read, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use termion::{async_stdin, AsyncReader};
 
+pub const DEFAULT_KEY_HOLD: Duration = Duration::from_millis(250);
+/// Terminals wait 500 to 660 ms before repeating a held key, against a few
+/// tens of ms between repeats.
+const FIRST_PRESS_HOLD: Duration = Duration::from_millis(700);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Press {
+    at: Instant,
+    repeated: bool,
+}
+
+/// Without release events a terminal only repeats the bytes of a held key, so
+/// a key counts as held for `FIRST_PRESS_HOLD` after it is pressed, to bridge
+/// the delay before the terminal repeats it, then for `hold_duration` after
+/// each repeat. Releases, when reported, end the hold straight away.
+#[derive(Debug, PartialEq, Clone)]
+struct KeyHold {
+    hold_duration: Duration,
+    releases_reported: bool,
+    last_pressed: [Option<Press>; KEYS_COUNT],
+    /// Keys pressed since the last poll, so that a press released before the
+    /// poll is still seen once.
+    tapped_keys: u16,
+}
+
+impl KeyHold {
+    fn new(hold_duration: Duration) -> Self {
+        KeyHold {
+            hold_duration,
+            releases_reported: false,
+            last_pressed: [None; KEYS_COUNT],
+            tapped_keys: 0,
+        }
+    }
+
+    fn press(&mut self, key: Key, now: Instant) {
+        let last_pressed = &mut self.last_pressed[key as usize];
+        *last_pressed = Some(Press {
+            at: now,
+            repeated: last_pressed.is_some(),
+        });
+        self.tapped_keys |= key.mask();
+    }
+
+    fn release(&mut self, key: Key) {
+        self.last_pressed[key as usize] = None;
+    }
+
+    fn get_held_keys(&mut self, now: Instant) -> u16 {
+        let mut held_keys = std::mem::replace(&mut self.tapped_keys, 0);
+
+        for (index, last_pressed) in self.last_pressed.iter_mut().enumerate() {
+            let is_held = match last_pressed {
+                Some(press) => {
+                    let hold_duration = if press.repeated {
+                        self.hold_duration
+                    } else {
+                        self.hold_duration.max(FIRST_PRESS_HOLD)
+                    };
+                    self.releases_reported || now.duration_since(press.at) < hold_duration
+                }
+                None => false,
+            };
+
+            if is_held {
+                held_keys |= 1 << index;
+            } else {
+                *last_pressed = None;
+            }
+        }
+
+        held_keys
+    }
+}
+
 pub struct ConsoleKeyboard {
     async_reader: RefCell<AsyncReader>,
     bytes_buffer: RefCell<Vec<u8>>,
+    keymap: Keymap,
+    key_hold: KeyHold,
+    host_events: VecDeque<HostEvent>,
 }
 
 impl ConsoleKeyboard {
-    pub fn new() -> Self {
+    pub fn new(keymap: Keymap, hold_duration: Duration) -> Self {
         ConsoleKeyboard {
             async_reader: RefCell::new(async_stdin()),
             bytes_buffer: RefCell::new(Vec::new()),
+            keymap,
+            key_hold: KeyHold::new(hold_duration),
+            host_events: VecDeque::new(),
+        }
+    }
+
+    /// Switches the terminal to the kitty keyboard protocol, which reports key
+    /// releases. Only call this after `input::supports_kitty_keyboard`.
+    pub fn enable_release_events(&mut self) {
+        let mut stdout = stdout();
+        if write!(stdout, "{}", input::KITTY_KEYBOARD_ENABLE)
+            .and_then(|_| stdout.flush())
+            .is_ok()
+        {
+            self.key_hold.releases_reported = true;
         }
     }
 
-    fn read_key(&self) -> Option<Key> {
+    /// Writes an escape sequence query to the terminal and collects its reply
+    /// up to `terminator`, giving up after `timeout`. Input read meanwhile is
+    /// dropped, so this only suits start-up.
+    pub fn query_terminal(&mut self, query: &str, terminator: u8, timeout: Duration) -> Vec<u8> {
+        let mut stdout = stdout();
+        if write!(stdout, "{}", query)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        let started = Instant::now();
+        let mut response = Vec::new();
+        while started.elapsed() < timeout && !response.ends_with(&[terminator]) {
+            if self
+                .async_reader
+                .borrow_mut()
+                .read_to_end(&mut response)
+                .is_err()
+            {
+                break;
+            }
+            sleep(Duration::from_millis(5));
+        }
+
+        response
+    }
+
+    /// Drains stdin, accumulating keypad keys until the next `get_held_keys`
+    /// and queueing host events, so that either can be polled first.
+    fn read_input(&mut self) {
         self.async_reader
             .borrow_mut()
             .read_to_end(&mut self.bytes_buffer.borrow_mut())
             .unwrap();
-        let mut buffer = self.bytes_buffer.borrow_mut();
-        let bytes = buffer.drain(..).collect::<Vec<u8>>();
+        let (inputs, length) = input::parse_input(&self.bytes_buffer.borrow());
+        self.bytes_buffer.borrow_mut().drain(..length);
 
-        if let Some(byte) = bytes.last() {
-            return self.match_byte(byte.clone());
+        let now = Instant::now();
+        for input in inputs {
+            match input {
+                Input::KeyRelease(character) => {
+                    if let Some(Binding::Keypad(key)) = self.keymap.get(character) {
+                        self.key_hold.release(key);
+                    }
+                    continue;
+                }
+                Input::Character(character) => match self.keymap.get(character) {
+                    Some(Binding::Keypad(key)) => self.key_hold.press(key, now),
+                    Some(Binding::Host(event)) => self.host_events.push_back(event),
+                    None => {}
+                },
+                Input::FunctionKey(function_key) => {
+                    if let Some(event) = get_debugger_event(function_key) {
+                        self.host_events.push_back(event);
+                    }
+                }
+            }
         }
+    }
+}
 
-        None
+/// Debugger controls sit on the function keys, out of the way of keymaps.
+fn get_debugger_event(function_key: u8) -> Option<HostEvent> {
+    match function_key {
+        5 => Some(HostEvent::TogglePause),
+        9 => Some(HostEvent::ToggleBreakpoint),
+        10 => Some(HostEvent::Step),
+        _ => None,
     }
+}
 
-    fn read_key_wait(&self) -> Key {
-        loop {
-            if let Some(key) = self.read_key() {
-                return key;
-            }
-        }
+impl Keyboard for ConsoleKeyboard {
+    fn get_held_keys(&mut self) -> u16 {
+        self.read_input();
+
+        self.key_hold.get_held_keys(Instant::now())
     }
 
-    fn match_byte(&self, key: u8) -> Option<Key> {
-        match key {
-            49 => Some(Key::Key1),
-            50 => Some(Key::Key2),
-            51 => Some(Key::Key3),
-            52 => Some(Key::KeyC),
-            113 => Some(Key::Key4),
-            119 => Some(Key::Key5),
-            101 => Some(Key::Key6),
-            114 => Some(Key::KeyD),
-            97 => Some(Key::Key7),
-            115 => Some(Key::Key8),
-            100 => Some(Key::Key9),
-            102 => Some(Key::KeyE),
-            122 => Some(Key::KeyA),
-            120 => Some(Key::Key0),
-            99 => Some(Key::KeyB),
-            118 => Some(Key::KeyF),
-            27 => Some(Key::KeyESC),
-            _ => None,
+    fn poll_host_event(&mut self) -> Option<HostEvent> {
+        self.read_input();
+
+        self.host_events.pop_front()
+    }
+}
+
+impl Drop for ConsoleKeyboard {
+    fn drop(&mut self) {
+        if self.key_hold.releases_reported {
+            let mut stdout = stdout();
+            write!(stdout, "{}", input::KITTY_KEYBOARD_DISABLE).unwrap();
+            stdout.flush().unwrap();
         }
     }
 }
 
-impl Keyboard for ConsoleKeyboard {
-    fn wait_for_key_press(&mut self) -> Key {
-        self.read_key_wait()
+#[cfg(test)]
+mod test_keyboard {
+    use super::*;
+
+    #[test]
+    fn test_key_is_held_until_hold_runs_out() {
+        let start = Instant::now();
+        let mut key_hold = KeyHold::new(Duration::from_millis(100));
+        key_hold.press(Key::Key5, start);
+
+        assert_eq!(
+            Key::Key5.mask(),
+            key_hold.get_held_keys(start + Duration::from_millis(50))
+        );
+
+        key_hold.press(Key::Key5, start + Duration::from_millis(80));
+        assert_eq!(
+            Key::Key5.mask(),
+            key_hold.get_held_keys(start + Duration::from_millis(150))
+        );
+        assert_eq!(
+            0,
+            key_hold.get_held_keys(start + Duration::from_millis(180))
+        );
     }
 
-    fn get_pressed_key(&mut self) -> Option<Key> {
-        self.read_key()
+    #[test]
+    fn test_first_press_is_held_until_repeats_start() {
+        let start = Instant::now();
+        let mut key_hold = KeyHold::new(DEFAULT_KEY_HOLD);
+        key_hold.press(Key::Key2, start);
+
+        assert_eq!(
+            Key::Key2.mask(),
+            key_hold.get_held_keys(start + Duration::from_millis(600))
+        );
+
+        key_hold.press(Key::Key2, start + Duration::from_millis(620));
+        assert_eq!(
+            Key::Key2.mask(),
+            key_hold.get_held_keys(start + Duration::from_millis(800))
+        );
+        assert_eq!(
+            0,
+            key_hold.get_held_keys(start + Duration::from_millis(900))
+        );
+    }
+
+    #[test]
+    fn test_reported_release_ends_hold() {
+        let start = Instant::now();
+        let mut key_hold = KeyHold::new(Duration::from_millis(100));
+        key_hold.releases_reported = true;
+        key_hold.press(Key::KeyA, start);
+
+        assert_eq!(
+            Key::KeyA.mask(),
+            key_hold.get_held_keys(start + Duration::from_secs(1))
+        );
+
+        key_hold.release(Key::KeyA);
+        assert_eq!(0, key_hold.get_held_keys(start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_tap_released_before_poll_is_seen_once() {
+        let start = Instant::now();
+        let mut key_hold = KeyHold::new(Duration::from_millis(100));
+        key_hold.releases_reported = true;
+        key_hold.press(Key::Key1, start);
+        key_hold.release(Key::Key1);
+
+        assert_eq!(Key::Key1.mask(), key_hold.get_held_keys(start));
+        assert_eq!(0, key_hold.get_held_keys(start));
     }
 }
@@ -1,3 +1,9 @@
+pub mod debugger;
 pub mod display;
+pub mod frame;
+pub mod graphics;
+pub mod input;
 pub mod keyboard;
 pub mod random_byte_generator;
+pub mod renderer;
+pub mod status;
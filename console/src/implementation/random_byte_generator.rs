@@ -1,9 +1,28 @@
 use chip8::chipset::RandomByteGenerator;
-use rand;
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
+use std::cell::RefCell;
+
+pub struct RandRandomByteGenerator {
+    rng: RefCell<StdRng>,
+}
+
+impl RandRandomByteGenerator {
+    /// Seeding makes `CXNN` produce the same sequence on every run.
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        RandRandomByteGenerator {
+            rng: RefCell::new(rng),
+        }
+    }
+}
 
-pub struct RandRandomByteGenerator {}
 impl RandomByteGenerator for RandRandomByteGenerator {
     fn generate(&self) -> u8 {
-        rand::random::<u8>()
+        self.rng.borrow_mut().gen::<u8>()
     }
 }
@@ -0,0 +1,168 @@
+use chip8::display::DirtyRegion;
+
+pub const RENDERERS: [&str; 3] = ["ascii", "half-block", "braille"];
+
+const BRAILLE_BLANK: u32 = 0x2800;
+// Dot bits of a Braille pattern, indexed by [y][x] inside its 2x4 cell.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// How pixels are packed into terminal cells.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Renderer {
+    /// One cell per pixel, stretched as cells are twice as tall as wide.
+    #[default]
+    Ascii,
+    /// Two vertically stacked pixels per cell, keeping them square.
+    HalfBlock,
+    /// 2x4 pixels per cell as Braille dots.
+    Braille,
+}
+
+impl Renderer {
+    pub fn from_name(name: &str) -> Option<Renderer> {
+        match name.to_ascii_lowercase().as_str() {
+            "ascii" => Some(Renderer::Ascii),
+            "half-block" | "halfblock" => Some(Renderer::HalfBlock),
+            "braille" => Some(Renderer::Braille),
+            _ => None,
+        }
+    }
+
+    /// Width and height in pixels covered by a single cell.
+    pub fn get_cell_size(self) -> (usize, usize) {
+        match self {
+            Renderer::Ascii => (1, 1),
+            Renderer::HalfBlock => (1, 2),
+            Renderer::Braille => (2, 4),
+        }
+    }
+
+    /// Cells needed for a `width` x `height` pixel screen.
+    pub fn get_cell_count(self, width: usize, height: usize) -> (usize, usize) {
+        let (cell_width, cell_height) = self.get_cell_size();
+
+        (width.div_ceil(cell_width), height.div_ceil(cell_height))
+    }
+
+    /// The cells, as opposed to pixels, that have to be redrawn for a region.
+    pub fn get_cell_region(self, region: &DirtyRegion) -> DirtyRegion {
+        let (cell_width, cell_height) = self.get_cell_size();
+        let x = region.x / cell_width;
+        let y = region.y / cell_height;
+        let (right, bottom) =
+            self.get_cell_count(region.x + region.width, region.y + region.height);
+
+        DirtyRegion::new(x, y, right - x, bottom - y)
+    }
+
+    /// Character for the cell at `column`, `row`. `is_lit` must return false
+    /// for pixels outside of the screen.
+    pub fn render_cell<P>(self, is_lit: P, column: usize, row: usize) -> char
+    where
+        P: Fn(usize, usize) -> bool,
+    {
+        let (cell_width, cell_height) = self.get_cell_size();
+        let x = column * cell_width;
+        let y = row * cell_height;
+
+        match self {
+            Renderer::Ascii => {
+                if is_lit(x, y) {
+                    '*'
+                } else {
+                    ' '
+                }
+            }
+            Renderer::HalfBlock => match (is_lit(x, y), is_lit(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            },
+            Renderer::Braille => {
+                let mut pattern = BRAILLE_BLANK;
+                for (dy, dots) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dx, dot) in dots.iter().enumerate() {
+                        if is_lit(x + dx, y + dy) {
+                            pattern |= dot;
+                        }
+                    }
+                }
+
+                std::char::from_u32(pattern).unwrap_or(' ')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_renderer {
+    use super::*;
+
+    #[test]
+    fn test_cell_counts() {
+        assert_eq!((64, 32), Renderer::Ascii.get_cell_count(64, 32));
+        assert_eq!((64, 16), Renderer::HalfBlock.get_cell_count(64, 32));
+        assert_eq!((32, 8), Renderer::Braille.get_cell_count(64, 32));
+        assert_eq!((64, 16), Renderer::Braille.get_cell_count(128, 64));
+    }
+
+    #[test]
+    fn test_cell_region_covers_partial_cells() {
+        let region = DirtyRegion::new(3, 5, 4, 2);
+
+        assert_eq!(
+            DirtyRegion::new(3, 2, 4, 2),
+            Renderer::HalfBlock.get_cell_region(&region)
+        );
+        assert_eq!(
+            DirtyRegion::new(1, 1, 3, 1),
+            Renderer::Braille.get_cell_region(&region)
+        );
+    }
+
+    #[test]
+    fn test_ascii_cells() {
+        assert_eq!('*', Renderer::Ascii.render_cell(|_, _| true, 0, 0));
+        assert_eq!(' ', Renderer::Ascii.render_cell(|_, _| false, 0, 0));
+    }
+
+    #[test]
+    fn test_half_block_cells() {
+        let is_lit = |_x: usize, y: usize| y == 1;
+
+        assert_eq!('▄', Renderer::HalfBlock.render_cell(is_lit, 0, 0));
+        assert_eq!(' ', Renderer::HalfBlock.render_cell(is_lit, 0, 1));
+    }
+
+    #[test]
+    fn test_braille_cells() {
+        assert_eq!('⣿', Renderer::Braille.render_cell(|_, _| true, 0, 0));
+        assert_eq!(
+            '⠁',
+            Renderer::Braille.render_cell(|x, y| x == 0 && y == 0, 0, 0)
+        );
+        assert_eq!(
+            '⢀',
+            Renderer::Braille.render_cell(|x, y| x == 1 && y == 3, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_high_resolution_corners() {
+        let mut memory = [[0_u8; 128]; 64];
+        memory[0][0] = 1;
+        memory[63][127] = 1;
+        let is_lit = |x: usize, y: usize| x < 128 && y < 64 && memory[y][x] == 1;
+
+        assert_eq!((128, 32), Renderer::HalfBlock.get_cell_count(128, 64));
+        assert_eq!('▀', Renderer::HalfBlock.render_cell(is_lit, 0, 0));
+        assert_eq!('▄', Renderer::HalfBlock.render_cell(is_lit, 127, 31));
+        assert_eq!(' ', Renderer::HalfBlock.render_cell(is_lit, 64, 16));
+
+        assert_eq!((64, 16), Renderer::Braille.get_cell_count(128, 64));
+        assert_eq!('⠁', Renderer::Braille.render_cell(is_lit, 0, 0));
+        assert_eq!('⢀', Renderer::Braille.render_cell(is_lit, 63, 15));
+        assert_eq!('⠀', Renderer::Braille.render_cell(is_lit, 32, 8));
+    }
+}
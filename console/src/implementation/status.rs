@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+/// How often the measured rates, and so the status line, are refreshed.
+pub const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Frames and instructions per second measured over `STATUS_INTERVAL`.
+pub struct StatusMeter {
+    window_start: Instant,
+    frames: usize,
+    instructions: usize,
+    frames_per_second: f32,
+    instructions_per_second: f32,
+}
+
+impl StatusMeter {
+    pub fn new(now: Instant) -> Self {
+        StatusMeter {
+            window_start: now,
+            frames: 0,
+            instructions: 0,
+            frames_per_second: 0.0,
+            instructions_per_second: 0.0,
+        }
+    }
+
+    /// Counts a frame and returns whether the rates were refreshed.
+    pub fn record_frame(&mut self, instructions: usize, now: Instant) -> bool {
+        self.frames += 1;
+        self.instructions += instructions;
+
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < STATUS_INTERVAL {
+            return false;
+        }
+
+        let seconds = elapsed.as_secs_f32();
+        self.frames_per_second = self.frames as f32 / seconds;
+        self.instructions_per_second = self.instructions as f32 / seconds;
+        self.window_start = now;
+        self.frames = 0;
+        self.instructions = 0;
+
+        true
+    }
+
+    pub fn get_frames_per_second(&self) -> f32 {
+        self.frames_per_second
+    }
+
+    pub fn get_instructions_per_second(&self) -> f32 {
+        self.instructions_per_second
+    }
+}
+
+/// What the emulator is doing, most important first.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RunState {
+    Running,
+    Paused,
+    WaitingForKey,
+}
+
+pub fn render_status(
+    meter: &StatusMeter,
+    speed_multiplier: f32,
+    sound_on: bool,
+    rom_name: &str,
+    run_state: RunState,
+) -> String {
+    let run_state = match run_state {
+        RunState::Running => "",
+        RunState::Paused => "  PAUSED",
+        RunState::WaitingForKey => "  WAITING FOR KEY",
+    };
+
+    format!(
+        "{:.0} fps  {:.0} ips  x{}  sound {}  {}{}",
+        meter.get_frames_per_second(),
+        meter.get_instructions_per_second(),
+        speed_multiplier,
+        if sound_on { "on" } else { "off" },
+        rom_name,
+        run_state
+    )
+}
+
+#[cfg(test)]
+mod test_status {
+    use super::*;
+
+    #[test]
+    fn test_rates_are_refreshed_after_interval() {
+        let start = Instant::now();
+        let mut meter = StatusMeter::new(start);
+
+        for frame in 1..30 {
+            assert!(!meter.record_frame(10, start + Duration::from_millis(frame * 16)));
+        }
+        assert!(meter.record_frame(10, start + Duration::from_millis(500)));
+
+        assert_eq!(60.0, meter.get_frames_per_second());
+        assert_eq!(600.0, meter.get_instructions_per_second());
+    }
+
+    #[test]
+    fn test_status_text() {
+        let start = Instant::now();
+        let mut meter = StatusMeter::new(start);
+        meter.record_frame(5, start + Duration::from_secs(1));
+
+        assert_eq!(
+            "1 fps  5 ips  x0.5  sound on  PONG  PAUSED",
+            render_status(&meter, 0.5, true, "PONG", RunState::Paused)
+        );
+        assert_eq!(
+            "1 fps  5 ips  x2  sound off  PONG",
+            render_status(&meter, 2.0, false, "PONG", RunState::Running)
+        );
+    }
+}
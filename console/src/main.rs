@@ -1,30 +1,336 @@
+mod cli;
 mod implementation;
 
+use chip8::chipset::MachineState;
+use chip8::host_event::HostEvent;
+use chip8::keymap::{Keymap, KeymapConfig};
+use chip8::rom;
 use chip8::Emulator;
-use implementation::display::ConsoleDisplay;
+use cli::{Command, Options, USAGE};
+use implementation::debugger;
+use implementation::display::{ConsoleDisplay, Output};
+use implementation::graphics::{self as terminal_graphics, GraphicsMode, GraphicsProtocol};
+use implementation::input;
 use implementation::keyboard::ConsoleKeyboard;
 use implementation::random_byte_generator::RandRandomByteGenerator;
+use implementation::status::{self, RunState, StatusMeter};
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io::{self, stdout, ErrorKind, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use termion::color;
+use termion::raw::IntoRawMode;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+const KEYMAP_ENV_VARIABLE: &str = "CHIP8_KEYMAP";
+const DEFAULT_KEYMAP_FILE: &str = ".chip8-keymap";
+const TERMINAL_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Message of the last panic, kept until the terminal is usable again.
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+/// The message cannot be printed from the hook as the terminal is still in
+/// raw mode, so it is kept for `take_panic_message` once unwinding restored
+/// the terminal. Colours and the cursor are reset right away in case it
+/// never is.
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let mut stdout = stdout();
+        let _ = write!(
+            stdout,
+            "{}{}{}",
+            color::Fg(color::Reset),
+            color::Bg(color::Reset),
+            termion::cursor::Show
+        )
+        .and_then(|_| stdout.flush());
+
+        if let Ok(mut message) = PANIC_MESSAGE.lock() {
+            *message = Some(info.to_string());
+        }
+    }));
+}
+
+fn take_panic_message() -> String {
+    PANIC_MESSAGE
+        .lock()
+        .ok()
+        .and_then(|mut message| message.take())
+        .unwrap_or_else(|| "unknown panic".to_owned())
+}
+
+/// Reads the keymap config named by `CHIP8_KEYMAP`, falling back to
+/// `~/.chip8-keymap` when it exists and then to the QWERTY preset.
+fn load_keymap(rom_path: &Path) -> Result<Keymap, String> {
+    let read_error = |path: &Path, error: io::Error| {
+        format!("cannot read keymap config `{}`: {}", path.display(), error)
+    };
+
+    let text = match env::var_os(KEYMAP_ENV_VARIABLE) {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            Some(fs::read_to_string(&path).map_err(|error| read_error(&path, error))?)
+        }
+        None => match env::var_os("HOME") {
+            Some(home) => {
+                let path = Path::new(&home).join(DEFAULT_KEYMAP_FILE);
+                match fs::read_to_string(&path) {
+                    Ok(text) => Some(text),
+                    Err(error) if error.kind() == ErrorKind::NotFound => None,
+                    Err(error) => return Err(read_error(&path, error)),
+                }
+            }
+            None => None,
+        },
+    };
+
+    let config = match text {
+        Some(text) => KeymapConfig::parse(&text)
+            .map_err(|error| format!("invalid keymap config, {}", error))?,
+        None => KeymapConfig::default(),
+    };
+
+    let rom_name = rom_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(config.keymap_for(&rom_name).clone())
+}
+
+fn load_rom(rom_path: &Path) -> Result<Vec<u8>, String> {
+    let rom = fs::read(rom_path).map_err(|error| match error.kind() {
+        ErrorKind::NotFound => format!("ROM `{}` does not exist", rom_path.display()),
+        _ => format!("cannot read ROM `{}`: {}", rom_path.display(), error),
+    })?;
+
+    rom::validate(&rom)
+        .map_err(|error| format!("cannot load `{}`: {}", rom_path.display(), error))?;
+
+    Ok(rom)
+}
+
+fn load_state(state_path: &Path) -> Result<MachineState, String> {
+    let bytes = fs::read(state_path).map_err(|error| {
+        format!(
+            "cannot read save state `{}`: {}",
+            state_path.display(),
+            error
+        )
+    })?;
+
+    MachineState::from_bytes(&bytes)
+        .map_err(|error| format!("cannot load `{}`: {}", state_path.display(), error))
+}
+
+/// Resolves `--graphics auto` by checking the environment for kitty and asking
+/// the terminal whether it speaks sixel. `None` falls back to text.
+fn detect_graphics(mode: GraphicsMode, keyboard: &mut ConsoleKeyboard) -> Option<GraphicsProtocol> {
+    match mode {
+        GraphicsMode::Off => None,
+        GraphicsMode::Forced(protocol) => Some(protocol),
+        GraphicsMode::Auto => {
+            if terminal_graphics::detect_kitty_from_environment() {
+                return Some(GraphicsProtocol::Kitty);
+            }
+
+            // Raw mode keeps the reply from being echoed or line buffered.
+            let _raw_terminal = stdout().into_raw_mode().ok()?;
+            let response = keyboard.query_terminal(
+                terminal_graphics::DEVICE_ATTRIBUTES_QUERY,
+                b'c',
+                TERMINAL_QUERY_TIMEOUT,
+            );
+
+            if terminal_graphics::supports_sixel(&response) {
+                Some(GraphicsProtocol::Sixel)
+            } else {
+                None
+            }
+        }
+    }
+}
 
-    let mut buffer = Vec::with_capacity(0x1000 - 0x200);
-    let mut rom = File::open(&args[1]).unwrap();
-    rom.read_to_end(&mut buffer).unwrap();
+/// Asks the terminal whether it speaks the kitty keyboard protocol, whose
+/// release events make the hold timeout unnecessary.
+fn detect_release_events(keyboard: &mut ConsoleKeyboard) -> bool {
+    let _raw_terminal = match stdout().into_raw_mode() {
+        Ok(raw_terminal) => raw_terminal,
+        Err(_) => return false,
+    };
+    let response =
+        keyboard.query_terminal(input::KITTY_KEYBOARD_QUERY, b'c', TERMINAL_QUERY_TIMEOUT);
 
-    let emulator = Emulator::new();
-    let keyboard = ConsoleKeyboard::new();
-    let display = ConsoleDisplay::new();
-    let random_byte_generator = RandRandomByteGenerator {};
+    input::supports_kitty_keyboard(&response)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let rom = load_rom(&options.rom_path)?;
+    let keymap = match options.keymap.clone() {
+        Some(keymap) => keymap,
+        None => load_keymap(&options.rom_path)?,
+    };
+    let machine_state = match &options.load_state {
+        Some(state_path) => Some(load_state(state_path)?),
+        None => None,
+    };
+
+    let mut emulator = Emulator::new();
+    emulator.set_quirks(options.quirks);
+    let mut keyboard = ConsoleKeyboard::new(keymap, options.key_hold);
+    if detect_release_events(&mut keyboard) {
+        keyboard.enable_release_events();
+    }
+    // The debugger panel is laid out in cells next to a text screen.
+    let graphics = if options.debug {
+        GraphicsMode::Off
+    } else {
+        options.graphics
+    };
+    let output = match detect_graphics(graphics, &mut keyboard) {
+        Some(protocol) => Output::Graphics(protocol),
+        None => Output::Text(options.renderer),
+    };
+    let mut display = ConsoleDisplay::new(output, options.theme, options.scale);
+    if options.debug {
+        display.reserve_side_panel(debugger::PANEL_WIDTH, debugger::PANEL_HEIGHT);
+    }
+    if options.status {
+        display.reserve_status_line();
+    }
+    let random_byte_generator = RandRandomByteGenerator::new(options.seed);
     let mut initialized_emulator =
-        emulator.initialize(&buffer, keyboard, display, random_byte_generator);
+        emulator.initialize(&rom, keyboard, display, random_byte_generator);
+
+    initialized_emulator.set_instructions_per_frame(options.get_instructions_per_frame());
+    if let Some(machine_state) = machine_state {
+        initialized_emulator.load_state(machine_state);
+    }
+    if options.paused {
+        initialized_emulator.handle_host_event(HostEvent::TogglePause);
+    }
+
+    let resized = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(&resized))
+        .map_err(|error| format!("cannot watch the terminal size: {}", error))?;
+
+    let rom_name = options
+        .rom_path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut status_meter = StatusMeter::new(Instant::now());
+
+    loop {
+        let frame_start = Instant::now();
+
+        if resized.swap(false, Ordering::Relaxed) {
+            initialized_emulator.get_display_mut().fit_terminal();
+            initialized_emulator.handle_host_event(HostEvent::Redraw);
+        }
+
+        // The machine state is reported along with the panic, after the
+        // emulator is dropped and the terminal restored.
+        let summary =
+            match panic::catch_unwind(AssertUnwindSafe(|| initialized_emulator.run_frame())) {
+                Ok(summary) => summary,
+                Err(_) => {
+                    let state = debugger::render_state(
+                        &initialized_emulator.get_debug_state(),
+                        initialized_emulator.is_paused(),
+                        initialized_emulator.get_breakpoints(),
+                        |address, length| initialized_emulator.read_memory(address, length),
+                    );
+                    return Err(format!(
+                        "emulator crashed, {}\n\n{}",
+                        take_panic_message(),
+                        state.join("\n")
+                    ));
+                }
+            };
+        if summary.halted.is_some() {
+            break;
+        }
+
+        if options.status
+            && status_meter.record_frame(summary.instructions_executed, Instant::now())
+        {
+            let run_state = if summary.paused {
+                RunState::Paused
+            } else if summary.waiting_for_key {
+                RunState::WaitingForKey
+            } else {
+                RunState::Running
+            };
+            let text = status::render_status(
+                &status_meter,
+                initialized_emulator.get_speed_multiplier(),
+                summary.sound_on,
+                &rom_name,
+                run_state,
+            );
+            initialized_emulator
+                .get_display_mut()
+                .draw_status_line(&text);
+        }
+
+        if options.debug {
+            let lines = debugger::render_panel(
+                &initialized_emulator.get_debug_state(),
+                initialized_emulator.is_paused(),
+                initialized_emulator.get_breakpoints(),
+                |address, length| initialized_emulator.read_memory(address, length),
+            );
+            initialized_emulator
+                .get_display_mut()
+                .draw_side_panel(&lines);
+        }
+
+        if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
+        }
+    }
+
+    // Keep the last in-memory save around so it can be passed to `--load-state`.
+    if let (Some(state_path), Some(machine_state)) =
+        (&options.save_state, initialized_emulator.get_saved_state())
+    {
+        fs::write(state_path, machine_state.to_bytes())
+            .map_err(|error| format!("cannot write `{}`: {}", state_path.display(), error))?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
 
-    while let Ok(()) = initialized_emulator.run_cycle() {
-        sleep(Duration::from_millis(2));
+    install_panic_hook();
+    match panic::catch_unwind(|| run(options)) {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+        Err(_) => {
+            eprintln!("error: {}", take_panic_message());
+            process::exit(101);
+        }
     }
 }
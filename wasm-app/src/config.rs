@@ -0,0 +1,217 @@
+use crate::implementation::display::{self, Rgba};
+use chip8::keymap::{Keymap, KeymapConfig};
+use chip8::quirks::{Quirks, PROFILES};
+use chip8::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use wasm_bindgen::prelude::*;
+
+pub const MAX_INSTRUCTIONS_PER_FRAME: usize = 1000;
+
+/// Quirks set one by one, overriding those of the profile or the platform.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+struct QuirkOverrides {
+    shift_uses_vy: Option<bool>,
+    load_store_increments_i: Option<bool>,
+    logic_resets_vf: Option<bool>,
+    jump_uses_vx: Option<bool>,
+}
+
+impl QuirkOverrides {
+    fn apply(self, quirks: Quirks) -> Quirks {
+        Quirks {
+            shift_uses_vy: self.shift_uses_vy.unwrap_or(quirks.shift_uses_vy),
+            load_store_increments_i: self
+                .load_store_increments_i
+                .unwrap_or(quirks.load_store_increments_i),
+            logic_resets_vf: self.logic_resets_vf.unwrap_or(quirks.logic_resets_vf),
+            jump_uses_vx: self.jump_uses_vx.unwrap_or(quirks.jump_uses_vx),
+        }
+    }
+}
+
+/// Settings applied by `Game::start`, for pages offering a settings panel.
+/// The quirks follow the platform detected for the ROM until a profile is
+/// set, and quirks set one by one apply on top of either.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct GameConfig {
+    profile: Option<Quirks>,
+    quirk_overrides: QuirkOverrides,
+    instructions_per_frame: usize,
+    foreground: Rgba,
+    background: Rgba,
+    keymap_config: KeymapConfig,
+}
+
+impl GameConfig {
+    /// The quirks to run a ROM detected as `detected` with.
+    pub fn get_quirks_or(&self, detected: Quirks) -> Quirks {
+        self.quirk_overrides.apply(self.profile.unwrap_or(detected))
+    }
+
+    pub fn get_instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
+    pub fn get_foreground(&self) -> Rgba {
+        self.foreground
+    }
+
+    pub fn get_background(&self) -> Rgba {
+        self.background
+    }
+
+    pub fn get_keymap_config(&self) -> &KeymapConfig {
+        &self.keymap_config
+    }
+}
+
+#[wasm_bindgen]
+impl GameConfig {
+    pub fn new() -> GameConfig {
+        GameConfig {
+            profile: None,
+            quirk_overrides: QuirkOverrides::default(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            foreground: display::DEFAULT_FOREGROUND,
+            background: display::DEFAULT_BACKGROUND,
+            keymap_config: KeymapConfig::default(),
+        }
+    }
+
+    /// One of the console's `--profile` names, or `auto` to go back to the
+    /// quirks of the detected platform. Drops the quirks set one by one.
+    pub fn set_profile(&mut self, profile: &str) -> Result<(), JsValue> {
+        if profile == "auto" {
+            self.profile = None;
+            self.quirk_overrides = QuirkOverrides::default();
+            return Ok(());
+        }
+
+        self.profile = Some(Quirks::from_profile(profile).ok_or_else(|| {
+            JsValue::from_str(&format!(
+                "unknown profile `{}`, expected auto or one of {}",
+                profile,
+                PROFILES.join(", ")
+            ))
+        })?);
+        self.quirk_overrides = QuirkOverrides::default();
+
+        Ok(())
+    }
+
+    pub fn set_shift_uses_vy(&mut self, enabled: bool) {
+        self.quirk_overrides.shift_uses_vy = Some(enabled);
+    }
+
+    pub fn set_load_store_increments_i(&mut self, enabled: bool) {
+        self.quirk_overrides.load_store_increments_i = Some(enabled);
+    }
+
+    pub fn set_logic_resets_vf(&mut self, enabled: bool) {
+        self.quirk_overrides.logic_resets_vf = Some(enabled);
+    }
+
+    pub fn set_jump_uses_vx(&mut self, enabled: bool) {
+        self.quirk_overrides.jump_uses_vx = Some(enabled);
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: usize) {
+        self.instructions_per_frame = instructions_per_frame.clamp(1, MAX_INSTRUCTIONS_PER_FRAME);
+    }
+
+    /// A CSS hex colour such as `#33ff66`.
+    pub fn set_foreground(&mut self, colour: &str) -> Result<(), JsValue> {
+        self.foreground = parse_colour(colour)?;
+
+        Ok(())
+    }
+
+    pub fn set_background(&mut self, colour: &str) -> Result<(), JsValue> {
+        self.background = parse_colour(colour)?;
+
+        Ok(())
+    }
+
+    /// Switches to one of the built-in layouts, dropping any loaded config.
+    pub fn set_keymap_layout(&mut self, layout: &str) -> bool {
+        match Keymap::from_preset(layout) {
+            Some(keymap) => {
+                self.keymap_config = KeymapConfig::new(keymap);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Loads a keymap config in the same format as the console's `~/.chip8-keymap`.
+    pub fn set_keymap_config(&mut self, config: &str) -> Result<(), JsValue> {
+        self.keymap_config =
+            KeymapConfig::parse(config).map_err(|error| JsValue::from_str(&error.to_string()))?;
+
+        Ok(())
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig::new()
+    }
+}
+
+fn parse_colour(colour: &str) -> Result<Rgba, JsValue> {
+    display::parse_colour(colour)
+        .ok_or_else(|| JsValue::from_str(&format!("`{}` is not a #rrggbb colour", colour)))
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+
+    #[test]
+    fn test_quirks_follow_platform_until_set() {
+        let mut config = GameConfig::new();
+        assert_eq!(Quirks::schip(), config.get_quirks_or(Quirks::schip()));
+
+        config.set_shift_uses_vy(true);
+        let expected = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::schip()
+        };
+        assert_eq!(expected, config.get_quirks_or(Quirks::schip()));
+
+        config.set_jump_uses_vx(false);
+        let expected = Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            ..Quirks::schip()
+        };
+        assert_eq!(expected, config.get_quirks_or(Quirks::schip()));
+
+        config.set_profile("cosmac").unwrap();
+        assert_eq!(Quirks::cosmac(), config.get_quirks_or(Quirks::schip()));
+
+        config.set_logic_resets_vf(false);
+        let expected = Quirks {
+            logic_resets_vf: false,
+            ..Quirks::cosmac()
+        };
+        assert_eq!(expected, config.get_quirks_or(Quirks::schip()));
+
+        config.set_profile("auto").unwrap();
+        assert_eq!(Quirks::schip(), config.get_quirks_or(Quirks::schip()));
+    }
+
+    #[test]
+    fn test_instructions_per_frame_are_clamped() {
+        let mut config = GameConfig::new();
+
+        config.set_instructions_per_frame(0);
+        assert_eq!(1, config.get_instructions_per_frame());
+
+        config.set_instructions_per_frame(usize::MAX);
+        assert_eq!(
+            MAX_INSTRUCTIONS_PER_FRAME,
+            config.get_instructions_per_frame()
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use wasm_bindgen::JsValue;
+use web_sys::{AudioContext, AudioContextState, GainNode, OscillatorNode, OscillatorType};
+
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.1;
+pub const MIN_FREQUENCY: f32 = 20.0;
+/// Same as the `max` of the pitch input on the page.
+pub const MAX_FREQUENCY: f32 = 2000.0;
+
+/// Pitches from JavaScript, NaN and infinities giving the default.
+pub fn clamp_frequency(frequency: f32) -> f32 {
+    if frequency.is_finite() {
+        frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY)
+    } else {
+        DEFAULT_FREQUENCY
+    }
+}
+
+pub fn clamp_volume(volume: f32) -> f32 {
+    if volume.is_finite() {
+        volume.clamp(0.0, 1.0)
+    } else {
+        DEFAULT_VOLUME
+    }
+}
+
+/// Square wave played while the sound timer runs. The oscillator never stops
+/// as an `OscillatorNode` cannot be started twice; the gain mutes it instead.
+pub struct BrowserBeeper {
+    context: AudioContext,
+    oscillator: OscillatorNode,
+    gain: GainNode,
+    volume: f32,
+    playing: bool,
+}
+
+impl BrowserBeeper {
+    pub fn new(frequency: f32, volume: f32) -> Result<BrowserBeeper, JsValue> {
+        let context = AudioContext::new()?;
+        let oscillator = context.create_oscillator()?;
+        let gain = context.create_gain()?;
+
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(frequency);
+        gain.gain().set_value(0.0);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&context.destination())?;
+        oscillator.start()?;
+
+        Ok(BrowserBeeper {
+            context,
+            oscillator,
+            gain,
+            volume,
+            playing: false,
+        })
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        if self.playing != playing {
+            self.playing = playing;
+            self.update_gain();
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.oscillator.frequency().set_value(frequency);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.update_gain();
+    }
+
+    /// Browsers keep audio suspended until the page gets a user gesture.
+    pub fn resume(&self) {
+        if self.context.state() == AudioContextState::Suspended {
+            let _ = self.context.resume();
+        }
+    }
+
+    fn update_gain(&self) {
+        let gain = if self.playing { self.volume } else { 0.0 };
+        self.gain.gain().set_value(gain);
+    }
+}
+
+impl Drop for BrowserBeeper {
+    fn drop(&mut self) {
+        let _ = self.oscillator.stop();
+        let _ = self.context.close();
+    }
+}
+
+#[cfg(test)]
+mod test_audio {
+    use super::*;
+
+    #[test]
+    fn test_clamp_frequency() {
+        assert_eq!(440.0, clamp_frequency(440.0));
+        assert_eq!(MIN_FREQUENCY, clamp_frequency(0.0));
+        assert_eq!(MAX_FREQUENCY, clamp_frequency(20_000.0));
+        assert_eq!(DEFAULT_FREQUENCY, clamp_frequency(f32::NAN));
+        assert_eq!(DEFAULT_FREQUENCY, clamp_frequency(f32::INFINITY));
+    }
+
+    #[test]
+    fn test_clamp_volume() {
+        assert_eq!(0.5, clamp_volume(0.5));
+        assert_eq!(0.0, clamp_volume(-1.0));
+        assert_eq!(1.0, clamp_volume(2.0));
+        assert_eq!(DEFAULT_VOLUME, clamp_volume(f32::NAN));
+        assert_eq!(DEFAULT_VOLUME, clamp_volume(f32::NEG_INFINITY));
+    }
+}
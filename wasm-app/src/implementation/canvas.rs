@@ -0,0 +1,94 @@
+use super::display::BrowserDisplay;
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, ImageData, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d,
+};
+
+/// A canvas on the page, or one handed to a worker with
+/// `transferControlToOffscreen`.
+#[derive(Clone)]
+pub enum Canvas {
+    Element(HtmlCanvasElement),
+    Offscreen(OffscreenCanvas),
+}
+
+/// Resolves a canvas given either as the element itself, an `OffscreenCanvas`
+/// or the id of an element.
+pub fn find_canvas(target: &JsValue) -> Result<Canvas, JsValue> {
+    if let Some(canvas) = target.dyn_ref::<OffscreenCanvas>() {
+        return Ok(Canvas::Offscreen(canvas.clone()));
+    }
+    if let Some(canvas) = target.dyn_ref::<HtmlCanvasElement>() {
+        return Ok(Canvas::Element(canvas.clone()));
+    }
+
+    let id = target
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("expected a canvas element or its id"))?;
+    let element = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("no document to look the canvas up in"))?
+        .get_element_by_id(&id)
+        .ok_or_else(|| JsValue::from_str(&format!("no element with id `{}`", id)))?;
+
+    element
+        .dyn_into::<HtmlCanvasElement>()
+        .map(Canvas::Element)
+        .map_err(|_| JsValue::from_str(&format!("element `{}` is not a canvas", id)))
+}
+
+enum Context {
+    Element(CanvasRenderingContext2d),
+    Offscreen(OffscreenCanvasRenderingContext2d),
+}
+
+/// Blits a `BrowserDisplay` onto its own canvas, sized to the framebuffer.
+pub struct CanvasBlitter {
+    context: Context,
+}
+
+impl CanvasBlitter {
+    pub fn new(canvas: &Canvas, display: &BrowserDisplay) -> Result<Self, JsValue> {
+        let (width, height) = (display.get_width() as u32, display.get_height() as u32);
+        let no_context = || JsValue::from_str("the canvas has no 2d context");
+
+        let context = match canvas {
+            Canvas::Element(canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+                Context::Element(
+                    canvas
+                        .get_context("2d")?
+                        .ok_or_else(no_context)?
+                        .dyn_into()?,
+                )
+            }
+            Canvas::Offscreen(canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+                Context::Offscreen(
+                    canvas
+                        .get_context("2d")?
+                        .ok_or_else(no_context)?
+                        .dyn_into()?,
+                )
+            }
+        };
+
+        Ok(CanvasBlitter { context })
+    }
+
+    pub fn blit(&self, display: &BrowserDisplay) -> Result<(), JsValue> {
+        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(display.get_pixels()),
+            display.get_width() as u32,
+            display.get_height() as u32,
+        )?;
+
+        match &self.context {
+            Context::Element(context) => context.put_image_data(&image_data, 0.0, 0.0),
+            Context::Offscreen(context) => context.put_image_data(&image_data, 0.0, 0.0),
+        }
+    }
+}
@@ -1,61 +1,147 @@
 use chip8::display::*;
 use std::ops;
-use wasm_bindgen::{JsCast, JsValue};
 
-const MULTIPLIER_X: f64 = 640.0 / 64.0;
-const MULTIPLIER_Y: f64 = 320.0 / 32.0;
+pub const DEFAULT_SCALE: usize = 10;
+pub const MAX_SCALE: usize = 32;
 
+pub type Rgba = [u8; 4];
+
+pub const DEFAULT_FOREGROUND: Rgba = [0x00, 0x00, 0x00, 0xff];
+pub const DEFAULT_BACKGROUND: Rgba = [0xff, 0xff, 0xff, 0xff];
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Reads a CSS hex colour, `#rgb` or `#rrggbb`, as given by
+/// `<input type="color">`.
+pub fn parse_colour(colour: &str) -> Option<Rgba> {
+    let digits = colour.strip_prefix('#')?;
+    if !digits.is_ascii() {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+
+    match digits.len() {
+        3 => {
+            let mut rgba = [0xff; 4];
+            for (index, digit) in digits.chars().enumerate() {
+                rgba[index] = channel(&digit.to_string())? * 0x11;
+            }
+            Some(rgba)
+        }
+        6 => Some([
+            channel(&digits[0..2])?,
+            channel(&digits[2..4])?,
+            channel(&digits[4..6])?,
+            0xff,
+        ]),
+        _ => None,
+    }
+}
+
+/// The screen as RGBA pixels, scaled up and with the palette applied, ready
+/// to be handed to an `ImageData` and blitted with a single `putImageData`.
 pub struct BrowserDisplay {
-    context: web_sys::CanvasRenderingContext2d,
-    fill_color_white: JsValue,
-    fill_color_black: JsValue,
+    pixels: Vec<u8>,
+    scale: usize,
+    foreground: Rgba,
+    background: Rgba,
 }
 
 impl BrowserDisplay {
-    pub fn new() -> BrowserDisplay {
-        let canvas = web_sys::window()
-            .unwrap()
-            .document()
-            .unwrap()
-            .get_element_by_id("game-canvas")
-            .unwrap();
-        let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into().map_err(|_| ()).unwrap();
-
-        let context = canvas
-            .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
+    pub fn new(scale: usize, foreground: Rgba, background: Rgba) -> BrowserDisplay {
+        let pixel_count = DISPLAY_WIDTH * scale * DISPLAY_HEIGHT * scale;
 
         BrowserDisplay {
-            context,
-            fill_color_white: JsValue::from_str("#FFFFFF"),
-            fill_color_black: JsValue::from_str("#000000"),
+            pixels: background.repeat(pixel_count),
+            scale,
+            foreground,
+            background,
+        }
+    }
+
+    pub fn get_pixels_ptr(&self) -> *const u8 {
+        self.pixels.as_ptr()
+    }
+
+    pub fn get_pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Width in image pixels.
+    pub fn get_width(&self) -> usize {
+        DISPLAY_WIDTH * self.scale
+    }
+
+    /// Height in image pixels.
+    pub fn get_height(&self) -> usize {
+        DISPLAY_HEIGHT * self.scale
+    }
+
+    fn fill_pixel(&mut self, x: usize, y: usize, colour: Rgba) {
+        let width = self.get_width();
+
+        for row in y * self.scale..(y + 1) * self.scale {
+            let start = (row * width + x * self.scale) * BYTES_PER_PIXEL;
+            let end = start + self.scale * BYTES_PER_PIXEL;
+
+            for pixel in self.pixels[start..end].chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel.copy_from_slice(&colour);
+            }
         }
     }
 }
 
 impl GraphicDisplay for BrowserDisplay {
-    fn draw<M>(&mut self, memory: &M)
+    fn draw<M>(&mut self, memory: &M, dirty_regions: &[DirtyRegion])
     where
         M: ops::Index<usize, Output = [u8]>,
     {
-        for y in 0..DISPLAY_HEIGHT {
-            for x in 0..DISPLAY_WIDTH {
-                if memory[y][x] == 1 {
-                    self.context.set_fill_style(&self.fill_color_black);
-                } else {
-                    self.context.set_fill_style(&self.fill_color_white);
+        for region in dirty_regions {
+            for y in region.y..region.y + region.height {
+                for x in region.x..region.x + region.width {
+                    let colour = if memory[y][x] == 1 {
+                        self.foreground
+                    } else {
+                        self.background
+                    };
+                    self.fill_pixel(x, y, colour);
                 }
-
-                self.context.fill_rect(
-                    x as f64 * MULTIPLIER_X,
-                    y as f64 * MULTIPLIER_Y,
-                    MULTIPLIER_X,
-                    MULTIPLIER_Y,
-                );
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test_display {
+    use super::*;
+    use chip8::gpu::GraphicMemory;
+
+    #[test]
+    fn test_parse_colour() {
+        assert_eq!(Some([0x12, 0xab, 0xef, 0xff]), parse_colour("#12abEF"));
+        assert_eq!(Some([0xff, 0x00, 0x88, 0xff]), parse_colour("#f08"));
+        assert_eq!(None, parse_colour("12abef"));
+        assert_eq!(None, parse_colour("#12ab"));
+        assert_eq!(None, parse_colour("#12abeg"));
+    }
+
+    #[test]
+    fn test_draw_scales_lit_pixels() {
+        let mut memory = GraphicMemory::new();
+        memory[0][1] = 1;
+        let mut display = BrowserDisplay::new(2, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND);
+
+        display.draw(&memory, &[DirtyRegion::full_screen()]);
+
+        let width = display.get_width();
+        let pixel = |x: usize, y: usize| {
+            let start = (y * width + x) * BYTES_PER_PIXEL;
+            &display.get_pixels()[start..start + BYTES_PER_PIXEL]
+        };
+        assert_eq!(&DEFAULT_BACKGROUND, pixel(1, 1));
+        assert_eq!(&DEFAULT_FOREGROUND, pixel(2, 0));
+        assert_eq!(&DEFAULT_FOREGROUND, pixel(3, 1));
+        assert_eq!(&DEFAULT_BACKGROUND, pixel(4, 0));
+        assert_eq!(&DEFAULT_BACKGROUND, pixel(2, 2));
+    }
+}
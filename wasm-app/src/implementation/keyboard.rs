@@ -1,52 +1,106 @@
-use chip8::keyboard::{Key, Keyboard};
+use super::keypad::TouchKeypad;
+use chip8::host_event::HostEvent;
+use chip8::keyboard::Keyboard;
+use chip8::keymap::{parse_key_name, Binding, Keymap};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::rc::Rc;
 
 pub struct BrowserKeyboard {
-    pressed_key: [u8; 1],
+    held_keys: u16,
+    keymap: Keymap,
+    host_events: VecDeque<HostEvent>,
+    /// Keys bound to host events that are down, so that the browser's
+    /// auto-repeat does not send the event again.
+    held_host_keys: BTreeSet<char>,
+    touch_keypad: Option<Rc<RefCell<TouchKeypad>>>,
 }
 
 impl BrowserKeyboard {
-    pub fn new() -> BrowserKeyboard {
-        BrowserKeyboard { pressed_key: [0] }
+    pub fn new(keymap: Keymap) -> BrowserKeyboard {
+        BrowserKeyboard {
+            held_keys: 0,
+            keymap,
+            host_events: VecDeque::new(),
+            held_host_keys: BTreeSet::new(),
+            touch_keypad: None,
+        }
     }
 
-    pub fn get_pressed_key_ptr(&self) -> *const u8 {
-        self.pressed_key.as_ptr()
+    /// Keys held on the on-screen keypad count as held too.
+    pub fn set_touch_keypad(&mut self, touch_keypad: Rc<RefCell<TouchKeypad>>) {
+        self.touch_keypad = Some(touch_keypad);
     }
 
-    fn read_key(&self) -> Option<Key> {
-        match self.pressed_key[0] {
-            49 => Some(Key::Key1),
-            50 => Some(Key::Key2),
-            51 => Some(Key::Key3),
-            52 => Some(Key::KeyC),
-            81 => Some(Key::Key4),
-            87 => Some(Key::Key5),
-            69 => Some(Key::Key6),
-            82 => Some(Key::KeyD),
-            65 => Some(Key::Key7),
-            83 => Some(Key::Key8),
-            68 => Some(Key::Key9),
-            70 => Some(Key::KeyE),
-            90 => Some(Key::KeyA),
-            88 => Some(Key::Key0),
-            67 => Some(Key::KeyB),
-            86 => Some(Key::KeyF),
-            27 => Some(Key::KeyESC),
-            _ => None,
+    /// Applies a `KeyboardEvent.key` through the keymap, returning whether it was bound.
+    pub fn set_key_held(&mut self, key_name: &str, held: bool) -> bool {
+        let character = match parse_key_name(key_name) {
+            Some(character) => character,
+            None => return false,
+        };
+
+        match self.keymap.get(character) {
+            Some(Binding::Keypad(key)) if held => self.held_keys |= key.mask(),
+            Some(Binding::Keypad(key)) => self.held_keys &= !key.mask(),
+            Some(Binding::Host(event)) => {
+                if !held {
+                    self.held_host_keys.remove(&character);
+                } else if self.held_host_keys.insert(character) {
+                    self.host_events.push_back(event);
+                }
+            }
+            None => return false,
         }
+
+        true
     }
 }
 
 impl Keyboard for BrowserKeyboard {
-    fn wait_for_key_press(&mut self) -> Key {
-        loop {
-            if let Some(key) = self.read_key() {
-                return key;
-            }
-        }
+    fn get_held_keys(&mut self) -> u16 {
+        self.held_keys
+            | self
+                .touch_keypad
+                .as_ref()
+                .map_or(0, |touch_keypad| touch_keypad.borrow().get_held_keys())
+    }
+
+    fn poll_host_event(&mut self) -> Option<HostEvent> {
+        self.host_events.pop_front()
     }
+}
+
+#[cfg(test)]
+mod test_keyboard {
+    use super::*;
+    use chip8::keyboard::Key;
+
+    #[test]
+    fn test_keypad_keys_are_held_until_released() {
+        let mut keyboard = BrowserKeyboard::new(Keymap::qwerty());
+
+        keyboard.set_key_held("1", true);
+        keyboard.set_key_held("v", true);
+        assert_eq!(
+            Key::Key1.mask() | Key::KeyF.mask(),
+            keyboard.get_held_keys()
+        );
+
+        keyboard.set_key_held("1", false);
+        assert_eq!(Key::KeyF.mask(), keyboard.get_held_keys());
+    }
+
+    #[test]
+    fn test_repeated_host_key_sends_one_event() {
+        let mut keyboard = BrowserKeyboard::new(Keymap::qwerty());
+
+        assert!(keyboard.set_key_held("p", true));
+        assert!(keyboard.set_key_held("p", true));
+        assert_eq!(Some(HostEvent::TogglePause), keyboard.poll_host_event());
+        assert_eq!(None, keyboard.poll_host_event());
 
-    fn get_pressed_key(&mut self) -> Option<Key> {
-        self.read_key()
+        keyboard.set_key_held("p", false);
+        keyboard.set_key_held("p", true);
+        assert_eq!(Some(HostEvent::TogglePause), keyboard.poll_host_event());
     }
 }
@@ -0,0 +1,246 @@
+use chip8::keyboard::Key;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Document, Element, HtmlCanvasElement, PointerEvent};
+
+/// The COSMAC VIP keypad, row by row.
+pub const KEYPAD_ROWS: [[Key; 4]; 4] = [
+    [Key::Key1, Key::Key2, Key::Key3, Key::KeyC],
+    [Key::Key4, Key::Key5, Key::Key6, Key::KeyD],
+    [Key::Key7, Key::Key8, Key::Key9, Key::KeyE],
+    [Key::KeyA, Key::Key0, Key::KeyB, Key::KeyF],
+];
+
+const KEY_COLOUR: &str = "#eeeeee";
+const HELD_KEY_COLOUR: &str = "#888888";
+const POINTER_EVENTS: [&str; 4] = ["pointerdown", "pointermove", "pointerup", "pointercancel"];
+
+/// The key each finger (or mouse button) rests on. A finger sliding from one
+/// key to another moves the press along, as on a real keypad.
+pub struct TouchKeypad {
+    pointers: BTreeMap<i32, Key>,
+}
+
+impl TouchKeypad {
+    pub fn new() -> TouchKeypad {
+        TouchKeypad {
+            pointers: BTreeMap::new(),
+        }
+    }
+
+    /// A pointer went down on `key`, or `None` when it is off the keys.
+    pub fn press(&mut self, pointer_id: i32, key: Option<Key>) {
+        match key {
+            Some(key) => self.pointers.insert(pointer_id, key),
+            None => self.pointers.remove(&pointer_id),
+        };
+    }
+
+    /// Only pointers already pressing a key drag it along, so hovering with
+    /// a mouse does nothing. The last key stays held over the gaps between
+    /// keys, `None` here, until the pointer is released.
+    pub fn slide(&mut self, pointer_id: i32, key: Option<Key>) {
+        if let (Some(held_key), Some(key)) = (self.pointers.get_mut(&pointer_id), key) {
+            *held_key = key;
+        }
+    }
+
+    pub fn release(&mut self, pointer_id: i32) {
+        self.pointers.remove(&pointer_id);
+    }
+
+    pub fn get_held_keys(&self) -> u16 {
+        self.pointers
+            .values()
+            .fold(0, |mask, key| mask | key.mask())
+    }
+}
+
+/// A 4x4 hex keypad inserted below the canvas for devices without a
+/// keyboard. It is removed from the page when dropped.
+pub struct VirtualKeypad {
+    container: Element,
+    state: Rc<RefCell<TouchKeypad>>,
+    listeners: Vec<Closure<dyn FnMut(PointerEvent)>>,
+}
+
+impl VirtualKeypad {
+    pub fn new(canvas: &HtmlCanvasElement, width: usize) -> Result<VirtualKeypad, JsValue> {
+        let document = canvas
+            .owner_document()
+            .ok_or_else(|| JsValue::from_str("the canvas is not in a document"))?;
+
+        let container = document.create_element("div")?;
+        container.set_attribute("class", "chip8-keypad")?;
+        container.set_attribute(
+            "style",
+            &format!(
+                "display: grid; grid-template-columns: repeat(4, 1fr); gap: 4px; \
+                 width: {}px; margin-top: 4px; touch-action: none; user-select: none",
+                width
+            ),
+        )?;
+
+        let mut buttons = Vec::new();
+        for key in KEYPAD_ROWS.iter().flatten() {
+            let button = document.create_element("button")?;
+            button.set_attribute("data-key", &format!("{:X}", *key as u8))?;
+            button.set_text_content(Some(&format!("{:X}", *key as u8)));
+            container.append_child(&button)?;
+            buttons.push((*key, button));
+        }
+        highlight(&buttons, 0)?;
+        canvas.after_with_node_1(&container)?;
+
+        let state = Rc::new(RefCell::new(TouchKeypad::new()));
+        let buttons = Rc::new(buttons);
+        let mut listeners = Vec::new();
+        for event_type in POINTER_EVENTS.iter() {
+            let state = Rc::clone(&state);
+            let buttons = Rc::clone(&buttons);
+            let document = document.clone();
+            let container_target = container.clone();
+            let listener = Closure::wrap(Box::new(move |event: PointerEvent| {
+                event.prevent_default();
+                let key = find_key(&document, &event);
+                let mut state = state.borrow_mut();
+                match event.type_().as_str() {
+                    "pointerdown" => {
+                        // Keeps the release coming here when it happens off the keypad.
+                        if let Some(target) = event.current_target() {
+                            let _ = target
+                                .unchecked_into::<Element>()
+                                .set_pointer_capture(event.pointer_id());
+                        }
+                        state.press(event.pointer_id(), key);
+                    }
+                    // Captured pointers keep coming here once off the keypad.
+                    "pointermove" if !is_over(&document, &container_target, &event) => {
+                        state.release(event.pointer_id())
+                    }
+                    "pointermove" => state.slide(event.pointer_id(), key),
+                    _ => state.release(event.pointer_id()),
+                }
+                let _ = highlight(&buttons, state.get_held_keys());
+            }) as Box<dyn FnMut(PointerEvent)>);
+
+            container
+                .add_event_listener_with_callback(event_type, listener.as_ref().unchecked_ref())?;
+            listeners.push(listener);
+        }
+
+        Ok(VirtualKeypad {
+            container,
+            state,
+            listeners,
+        })
+    }
+
+    /// Shared with the keyboard, which adds these keys to the ones typed.
+    pub fn get_state(&self) -> Rc<RefCell<TouchKeypad>> {
+        Rc::clone(&self.state)
+    }
+}
+
+impl Drop for VirtualKeypad {
+    fn drop(&mut self) {
+        for (event_type, listener) in POINTER_EVENTS.iter().zip(&self.listeners) {
+            let _ = self
+                .container
+                .remove_event_listener_with_callback(event_type, listener.as_ref().unchecked_ref());
+        }
+        self.container.remove();
+    }
+}
+
+fn is_over(document: &Document, container: &Element, event: &PointerEvent) -> bool {
+    document
+        .element_from_point(event.client_x() as f32, event.client_y() as f32)
+        .is_some_and(|element| container.contains(Some(&element)))
+}
+
+/// Touch pointers stay captured by the button they went down on, so the key
+/// under a sliding finger is looked up from its position.
+fn find_key(document: &Document, event: &PointerEvent) -> Option<Key> {
+    let element = document.element_from_point(event.client_x() as f32, event.client_y() as f32)?;
+    let index = u8::from_str_radix(&element.get_attribute("data-key")?, 16).ok()?;
+
+    Key::from_index(index)
+}
+
+fn highlight(buttons: &[(Key, Element)], held_keys: u16) -> Result<(), JsValue> {
+    for (key, button) in buttons {
+        let (class, colour) = if held_keys & key.mask() != 0 {
+            ("chip8-keypad-key held", HELD_KEY_COLOUR)
+        } else {
+            ("chip8-keypad-key", KEY_COLOUR)
+        };
+        button.set_attribute("class", class)?;
+        button.set_attribute(
+            "style",
+            &format!(
+                "font: bold 24px monospace; padding: 16px 0; border: 1px solid black; \
+                 border-radius: 4px; background: {}",
+                colour
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_keypad {
+    use super::*;
+
+    #[test]
+    fn test_several_fingers_hold_several_keys() {
+        let mut keypad = TouchKeypad::new();
+        keypad.press(1, Some(Key::Key5));
+        keypad.press(2, Some(Key::KeyA));
+
+        assert_eq!(Key::Key5.mask() | Key::KeyA.mask(), keypad.get_held_keys());
+
+        keypad.release(1);
+        assert_eq!(Key::KeyA.mask(), keypad.get_held_keys());
+    }
+
+    #[test]
+    fn test_sliding_moves_the_press() {
+        let mut keypad = TouchKeypad::new();
+        keypad.slide(1, Some(Key::Key4));
+        assert_eq!(0, keypad.get_held_keys());
+
+        keypad.press(1, Some(Key::Key4));
+        keypad.slide(1, Some(Key::Key6));
+        assert_eq!(Key::Key6.mask(), keypad.get_held_keys());
+
+        keypad.release(1);
+        assert_eq!(0, keypad.get_held_keys());
+    }
+
+    #[test]
+    fn test_sliding_across_a_gap_keeps_the_press() {
+        let mut keypad = TouchKeypad::new();
+        keypad.press(1, Some(Key::Key4));
+
+        keypad.slide(1, None);
+        assert_eq!(Key::Key4.mask(), keypad.get_held_keys());
+
+        keypad.slide(1, Some(Key::Key5));
+        assert_eq!(Key::Key5.mask(), keypad.get_held_keys());
+    }
+
+    #[test]
+    fn test_rows_hold_every_key_once() {
+        let mask = KEYPAD_ROWS
+            .iter()
+            .flatten()
+            .fold(0, |mask: u16, key| mask | key.mask());
+
+        assert_eq!(0xffff, mask);
+    }
+}
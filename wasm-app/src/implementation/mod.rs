@@ -1,3 +1,7 @@
+pub mod audio;
+pub mod canvas;
 pub mod display;
 pub mod keyboard;
+pub mod keypad;
 pub mod random_byte_generator;
+pub mod storage;
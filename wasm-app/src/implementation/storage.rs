@@ -0,0 +1,128 @@
+use chip8::chipset::{PersistentFlags, PERSISTENT_FLAGS_COUNT};
+use std::fmt::Write;
+use wasm_bindgen::JsValue;
+use web_sys::Storage;
+
+const KEY_PREFIX: &str = "chip8";
+
+/// FNV-1a of the ROM, so saves follow the game rather than its file name.
+pub fn hash_rom(rom: &[u8]) -> String {
+    let hash = rom.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+/// localStorage only holds strings.
+fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(text, "{:02x}", byte);
+    }
+
+    text
+}
+
+fn decode(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn slot_key(rom_hash: &str, slot: u32) -> String {
+    format!("{}/{}/slot/{}", KEY_PREFIX, rom_hash, slot)
+}
+
+fn flags_key(rom_hash: &str) -> String {
+    format!("{}/{}/flags", KEY_PREFIX, rom_hash)
+}
+
+/// Numbered save slots and the persistent flags of one ROM, kept in
+/// localStorage.
+pub struct RomStorage {
+    storage: Storage,
+    rom_hash: String,
+}
+
+impl RomStorage {
+    pub fn new(rom: &[u8]) -> Result<RomStorage, JsValue> {
+        let storage = web_sys::window()
+            .ok_or_else(|| JsValue::from_str("no window to get localStorage from"))?
+            .local_storage()?
+            .ok_or_else(|| JsValue::from_str("localStorage is not available"))?;
+
+        Ok(RomStorage {
+            storage,
+            rom_hash: hash_rom(rom),
+        })
+    }
+
+    pub fn save_slot(&self, slot: u32, state: &[u8]) -> Result<(), JsValue> {
+        self.storage
+            .set_item(&slot_key(&self.rom_hash, slot), &encode(state))
+    }
+
+    pub fn load_slot(&self, slot: u32) -> Result<Option<Vec<u8>>, JsValue> {
+        match self.storage.get_item(&slot_key(&self.rom_hash, slot))? {
+            Some(text) => decode(&text)
+                .map(Some)
+                .ok_or_else(|| JsValue::from_str(&format!("save slot {} is corrupted", slot))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_slot(&self, slot: u32) -> Result<(), JsValue> {
+        self.storage.remove_item(&slot_key(&self.rom_hash, slot))
+    }
+
+    pub fn save_flags(&self, flags: &PersistentFlags) -> Result<(), JsValue> {
+        self.storage
+            .set_item(&flags_key(&self.rom_hash), &encode(flags))
+    }
+
+    /// Flags that cannot be read back are treated as never saved.
+    pub fn load_flags(&self) -> Result<Option<PersistentFlags>, JsValue> {
+        let text = match self.storage.get_item(&flags_key(&self.rom_hash))? {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+
+        Ok(decode(&text)
+            .filter(|bytes| bytes.len() == PERSISTENT_FLAGS_COUNT)
+            .map(|bytes| {
+                let mut flags = [0; PERSISTENT_FLAGS_COUNT];
+                flags.copy_from_slice(&bytes);
+                flags
+            }))
+    }
+}
+
+#[cfg(test)]
+mod test_storage {
+    use super::*;
+
+    #[test]
+    fn test_hash_rom_depends_on_content() {
+        assert_eq!("cbf29ce484222325", hash_rom(&[]));
+        assert_eq!(hash_rom(&[0x00, 0xe0]), hash_rom(&[0x00, 0xe0]));
+        assert_ne!(hash_rom(&[0x00, 0xe0]), hash_rom(&[0xe0, 0x00]));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let bytes = [0x00, 0x7f, 0xff, 0x10];
+
+        assert_eq!("007fff10", encode(&bytes));
+        assert_eq!(Some(bytes.to_vec()), decode(&encode(&bytes)));
+        assert_eq!(None, decode("007"));
+        assert_eq!(None, decode("zz"));
+    }
+
+    #[test]
+    fn test_keys_are_per_rom() {
+        assert_eq!("chip8/0123/slot/2", slot_key("0123", 2));
+        assert_eq!("chip8/0123/flags", flags_key("0123"));
+    }
+}
@@ -1,13 +1,25 @@
 #[macro_use]
 mod utils;
+mod config;
 mod implementation;
+mod protocol;
+mod worker;
 
+use chip8::chipset::{CycleOutcome, DebugState, MachineState, PersistentFlags};
+use chip8::disassembler;
 use chip8::gpu::Chip8Gpu;
+use chip8::host_event::HostEvent;
 use chip8::opcode_processor::Chip8OpCodesProcessor;
+use chip8::rom::{self, Platform};
 use chip8::{Emulator, InitializedEmulator};
-use implementation::display::BrowserDisplay;
+use config::GameConfig;
+use implementation::audio::{self, BrowserBeeper};
+use implementation::canvas::{self, Canvas, CanvasBlitter};
+use implementation::display::{self, BrowserDisplay};
 use implementation::keyboard::BrowserKeyboard;
+use implementation::keypad::VirtualKeypad;
 use implementation::random_byte_generator::RandRandomByteGenerator;
+use implementation::storage::RomStorage;
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -19,36 +31,243 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[wasm_bindgen]
 pub struct Game {
     rom: Vec<u8>,
+    rom_name: String,
+    platform: Platform,
+    config: GameConfig,
+    sound_frequency: f32,
+    sound_volume: f32,
+    scale: usize,
+    canvas: Option<Canvas>,
+    virtual_keypad: bool,
 }
 
+/// Every `Game` and the `RunningGame`s it starts are independent, so several
+/// can share a page, each drawing to its own canvas.
 #[wasm_bindgen]
 impl Game {
     pub fn new() -> Game {
         utils::set_panic_hook();
         Game {
-            rom: vec![0; 0xe00],
+            rom: Vec::new(),
+            rom_name: String::new(),
+            platform: Platform::Chip8,
+            config: GameConfig::new(),
+            sound_frequency: audio::DEFAULT_FREQUENCY,
+            sound_volume: audio::DEFAULT_VOLUME,
+            scale: display::DEFAULT_SCALE,
+            canvas: None,
+            virtual_keypad: false,
         }
     }
 
-    pub fn get_rom_ptr(&self) -> *const u8 {
-        self.rom.as_ptr()
+    /// The canvas to draw to, given as the element, its id or an
+    /// `OffscreenCanvas`. Without one the page has to blit the framebuffer
+    /// itself.
+    pub fn set_canvas(&mut self, target: JsValue) -> Result<(), JsValue> {
+        self.canvas = Some(canvas::find_canvas(&target)?);
+
+        Ok(())
+    }
+
+    /// Puts a touchable hex keypad below the canvas, for devices without a
+    /// keyboard. Needs a canvas element, not an `OffscreenCanvas`.
+    pub fn set_virtual_keypad(&mut self, enabled: bool) {
+        self.virtual_keypad = enabled;
+    }
+
+    /// Size in image pixels of a CHIP-8 pixel in the framebuffer.
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.clamp(1, display::MAX_SCALE);
+    }
+
+    /// Pitch of the beep in Hz.
+    pub fn set_sound_frequency(&mut self, frequency: f32) {
+        self.sound_frequency = audio::clamp_frequency(frequency);
+    }
+
+    /// Volume of the beep from 0 (muted) to 1.
+    pub fn set_sound_volume(&mut self, volume: f32) {
+        self.sound_volume = audio::clamp_volume(volume);
     }
 
-    pub fn start(&mut self) -> RunningGame {
-        let emulator = Emulator::new();
-        let keyboard = BrowserKeyboard::new();
-        let display = BrowserDisplay::new();
+    /// Takes a copy of the ROM, throwing if it does not fit in memory, and
+    /// picks the platform from its file name.
+    pub fn load_rom(&mut self, rom: &[u8], file_name: &str) -> Result<(), JsValue> {
+        rom::validate(rom).map_err(|error| JsValue::from_str(&error.to_string()))?;
+
+        self.rom = rom.to_vec();
+        self.rom_name = file_name.to_owned();
+        self.platform = Platform::detect(file_name);
+
+        Ok(())
+    }
+
+    /// `chip8` or `schip`, as detected by `load_rom`.
+    pub fn get_platform(&self) -> String {
+        self.platform.get_name().to_owned()
+    }
+
+    /// Takes a copy of the settings, used by every later `start`.
+    pub fn set_config(&mut self, config: &GameConfig) {
+        self.config = config.clone();
+    }
+
+    /// Same as `GameConfig::set_keymap_layout` on the current settings.
+    pub fn set_keymap_layout(&mut self, layout: &str) -> bool {
+        self.config.set_keymap_layout(layout)
+    }
+
+    /// Same as `GameConfig::set_keymap_config` on the current settings.
+    pub fn set_keymap_config(&mut self, config: &str) -> Result<(), JsValue> {
+        self.config.set_keymap_config(config)
+    }
+
+    pub fn start(&mut self) -> Result<RunningGame, JsValue> {
+        if self.rom.is_empty() {
+            return Err(JsValue::from_str("no ROM loaded"));
+        }
+
+        let mut emulator = Emulator::new();
+        emulator.set_quirks(self.config.get_quirks_or(self.platform.get_quirks()));
+        let keymap_config = self.config.get_keymap_config();
+        let mut keyboard = BrowserKeyboard::new(keymap_config.keymap_for(&self.rom_name).clone());
+        let display = BrowserDisplay::new(
+            self.scale,
+            self.config.get_foreground(),
+            self.config.get_background(),
+        );
         let random_byte_generator = RandRandomByteGenerator::new();
 
-        let initialized_emulator =
+        let blitter = match &self.canvas {
+            Some(canvas) => Some(CanvasBlitter::new(canvas, &display)?),
+            None => None,
+        };
+        let keypad = match (&self.canvas, self.virtual_keypad) {
+            (_, false) => None,
+            (Some(Canvas::Element(canvas)), true) => {
+                Some(VirtualKeypad::new(canvas, display.get_width())?)
+            }
+            (_, true) => {
+                return Err(JsValue::from_str(
+                    "the virtual keypad needs a canvas element to sit under",
+                ))
+            }
+        };
+        if let Some(keypad) = &keypad {
+            keyboard.set_touch_keypad(keypad.get_state());
+        }
+        let mut initialized_emulator =
             emulator.initialize(&self.rom, keyboard, display, random_byte_generator);
+        initialized_emulator.set_instructions_per_frame(self.config.get_instructions_per_frame());
+
+        // Without localStorage, e.g. in a private window, nothing is kept.
+        let storage = RomStorage::new(&self.rom).ok();
+        if let Some(flags) = storage
+            .as_ref()
+            .and_then(|storage| storage.load_flags().ok()?)
+        {
+            initialized_emulator.set_persistent_flags(flags);
+        }
+        let stored_flags = *initialized_emulator.get_persistent_flags();
+
+        // Without Web Audio the game simply runs silent.
+        let beeper = BrowserBeeper::new(self.sound_frequency, self.sound_volume).ok();
 
-        RunningGame {
+        let running_game = RunningGame {
             emulator: initialized_emulator,
+            beeper,
+            blitter,
+            screen_changed: false,
+            sound_on: false,
+            _keypad: keypad,
+            storage,
+            stored_flags,
+        };
+        running_game.blit()?;
+
+        Ok(running_game)
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CycleStatus {
+    Executed,
+    Drew,
+    WaitingForKey,
+    SoundStarted,
+    SoundStopped,
+    Halted,
+    BreakpointHit,
+    Paused,
+}
+
+impl From<CycleOutcome> for CycleStatus {
+    fn from(outcome: CycleOutcome) -> Self {
+        match outcome {
+            CycleOutcome::Executed => CycleStatus::Executed,
+            CycleOutcome::Drew => CycleStatus::Drew,
+            CycleOutcome::WaitingForKey => CycleStatus::WaitingForKey,
+            CycleOutcome::SoundStarted => CycleStatus::SoundStarted,
+            CycleOutcome::SoundStopped => CycleStatus::SoundStopped,
+            CycleOutcome::Halted(_) => CycleStatus::Halted,
+            CycleOutcome::BreakpointHit => CycleStatus::BreakpointHit,
+            CycleOutcome::Paused => CycleStatus::Paused,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HostCommand {
+    Quit,
+    TogglePause,
+    Reset,
+    SaveState,
+    LoadState,
+    SpeedUp,
+    SlowDown,
+    Redraw,
+    Step,
+    ToggleBreakpoint,
+}
+
+impl From<HostCommand> for HostEvent {
+    fn from(command: HostCommand) -> Self {
+        match command {
+            HostCommand::Quit => HostEvent::Quit,
+            HostCommand::TogglePause => HostEvent::TogglePause,
+            HostCommand::Reset => HostEvent::Reset,
+            HostCommand::SaveState => HostEvent::SaveState,
+            HostCommand::LoadState => HostEvent::LoadState,
+            HostCommand::SpeedUp => HostEvent::SpeedUp,
+            HostCommand::SlowDown => HostEvent::SlowDown,
+            HostCommand::Redraw => HostEvent::Redraw,
+            HostCommand::Step => HostEvent::Step,
+            HostCommand::ToggleBreakpoint => HostEvent::ToggleBreakpoint,
         }
     }
 }
 
+/// One line of `RunningGame::disassemble`.
+#[wasm_bindgen]
+pub struct DisassembledInstruction {
+    pub address: u16,
+    pub opcode: u16,
+    pub breakpoint: bool,
+    pub current: bool,
+    text: String,
+}
+
+#[wasm_bindgen]
+impl DisassembledInstruction {
+    /// The mnemonic, e.g. `LD V0, 0x05`.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+}
+
 #[wasm_bindgen]
 pub struct RunningGame {
     emulator: InitializedEmulator<
@@ -58,18 +277,273 @@ pub struct RunningGame {
         BrowserDisplay,
         RandRandomByteGenerator,
     >,
+    beeper: Option<BrowserBeeper>,
+    blitter: Option<CanvasBlitter>,
+    screen_changed: bool,
+    sound_on: bool,
+    /// Kept for as long as the game runs, dropping it takes it off the page.
+    _keypad: Option<VirtualKeypad>,
+    storage: Option<RomStorage>,
+    stored_flags: PersistentFlags,
 }
 
 #[wasm_bindgen]
 impl RunningGame {
-    pub fn run_cycle(&mut self) -> bool {
-        match self.emulator.run_cycle() {
-            Ok(()) => true,
-            _ => false,
+    /// Runs a single cycle, drawing to the canvas and beeping like `run_frame`.
+    pub fn run_cycle(&mut self) -> Result<CycleStatus, JsValue> {
+        let outcome = self.emulator.run_cycle();
+        self.screen_changed = outcome == CycleOutcome::Drew;
+
+        let running = !matches!(outcome, CycleOutcome::Halted(_));
+        self.sound_on = running && !self.emulator.is_paused() && self.emulator.is_sound_on();
+        if let Some(beeper) = &mut self.beeper {
+            beeper.set_playing(self.sound_on);
         }
+        if self.screen_changed {
+            self.blit()?;
+        }
+        self.store_flags();
+
+        Ok(CycleStatus::from(outcome))
     }
 
-    pub fn get_pressed_key_ptr(&self) -> *const u8 {
-        self.emulator.get_keyboard().get_pressed_key_ptr()
+    /// Runs the frames due after `elapsed_ms` milliseconds, as passed between
+    /// `requestAnimationFrame` callbacks, and draws to the canvas if the game
+    /// has one. Returns false once the game halted.
+    pub fn run_frame(&mut self, elapsed_ms: f64) -> Result<bool, JsValue> {
+        let summary = self.emulator.run_for(elapsed_ms);
+        let running = summary.halted.is_none();
+        self.screen_changed = summary.screen_changed;
+
+        self.sound_on = running && !summary.paused && summary.sound_on;
+        if let Some(beeper) = &mut self.beeper {
+            beeper.set_playing(self.sound_on);
+        }
+        if self.screen_changed {
+            self.blit()?;
+        }
+        self.store_flags();
+
+        Ok(running)
+    }
+
+    /// Writes the persistent flags whenever the game changed them. A full
+    /// localStorage should not stop the game, so failures are ignored.
+    fn store_flags(&mut self) {
+        let flags = *self.emulator.get_persistent_flags();
+        if flags == self.stored_flags {
+            return;
+        }
+
+        self.stored_flags = flags;
+        if let Some(storage) = &self.storage {
+            let _ = storage.save_flags(&flags);
+        }
+    }
+
+    /// The complete machine state, for the page to keep wherever it likes.
+    pub fn export_state(&self) -> Vec<u8> {
+        self.emulator.save_state().to_bytes()
+    }
+
+    /// Restores a state from `export_state`, throwing if it is not one.
+    pub fn import_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        let machine_state = MachineState::from_bytes(state)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.emulator.load_state(machine_state);
+
+        Ok(())
+    }
+
+    fn get_storage(&self) -> Result<&RomStorage, JsValue> {
+        self.storage
+            .as_ref()
+            .ok_or_else(|| JsValue::from_str("localStorage is not available"))
+    }
+
+    /// Keeps the current state in a localStorage slot of this ROM.
+    pub fn save_slot(&self, slot: u32) -> Result<(), JsValue> {
+        self.get_storage()?.save_slot(slot, &self.export_state())
+    }
+
+    /// Restores a slot written by `save_slot`. Returns false if it is empty.
+    pub fn load_slot(&mut self, slot: u32) -> Result<bool, JsValue> {
+        match self.get_storage()?.load_slot(slot)? {
+            Some(state) => self.import_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn delete_slot(&self, slot: u32) -> Result<(), JsValue> {
+        self.get_storage()?.delete_slot(slot)
+    }
+
+    fn blit(&self) -> Result<(), JsValue> {
+        match &self.blitter {
+            Some(blitter) => blitter.blit(self.emulator.get_display()),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the game beeped at the end of the last `run_frame` or `run_cycle`.
+    pub fn is_sound_on(&self) -> bool {
+        self.sound_on
+    }
+
+    /// Whether the framebuffer changed during the last `run_frame` or `run_cycle`.
+    pub fn is_screen_changed(&self) -> bool {
+        self.screen_changed
+    }
+
+    /// RGBA pixels of the screen, `get_framebuffer_width()` by
+    /// `get_framebuffer_height()`. Views on it go stale when wasm memory grows,
+    /// so take a fresh one for every blit.
+    pub fn get_framebuffer_ptr(&self) -> *const u8 {
+        self.emulator.get_display().get_pixels_ptr()
+    }
+
+    pub fn get_framebuffer_width(&self) -> usize {
+        self.emulator.get_display().get_width()
+    }
+
+    pub fn get_framebuffer_height(&self) -> usize {
+        self.emulator.get_display().get_height()
+    }
+
+    /// Call from a user gesture handler, browsers start audio suspended.
+    pub fn resume_audio(&self) {
+        if let Some(beeper) = &self.beeper {
+            beeper.resume();
+        }
+    }
+
+    pub fn set_sound_frequency(&mut self, frequency: f32) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.set_frequency(audio::clamp_frequency(frequency));
+        }
+    }
+
+    pub fn set_sound_volume(&mut self, volume: f32) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.set_volume(audio::clamp_volume(volume));
+        }
+    }
+
+    pub fn set_key_held(&mut self, key_name: &str, held: bool) -> bool {
+        self.emulator
+            .get_keyboard_mut()
+            .set_key_held(key_name, held)
+    }
+
+    pub fn send_host_command(&mut self, command: HostCommand) {
+        self.emulator.handle_host_event(HostEvent::from(command));
+    }
+
+    fn get_debug_state(&self) -> DebugState {
+        self.emulator.get_debug_state()
+    }
+
+    /// V0 to VF.
+    pub fn get_registers(&self) -> Vec<u8> {
+        self.get_debug_state().registers.to_vec()
+    }
+
+    pub fn get_address_register(&self) -> u16 {
+        self.get_debug_state().address_register
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.get_debug_state().program_counter
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.get_debug_state().delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.get_debug_state().sound_timer
+    }
+
+    /// Return addresses, the innermost call last.
+    pub fn get_stack(&self) -> Vec<u16> {
+        self.get_debug_state().stack
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.get_debug_state().waiting_for_key
+    }
+
+    /// Up to `length` bytes from `address`, fewer at the end of memory.
+    pub fn read_memory(&self, address: u16, length: usize) -> Vec<u8> {
+        self.emulator.read_memory(address, length)
+    }
+
+    /// `count` instructions with PC in the middle, or as close to it as the
+    /// start of memory allows.
+    pub fn disassemble(&self, count: usize) -> Vec<DisassembledInstruction> {
+        let program_counter = self.get_program_counter();
+        let before = (count / 2).min(usize::from(program_counter / 2)) as u16;
+        let start = program_counter - before * 2;
+        let breakpoints = self.emulator.get_breakpoints();
+
+        self.read_memory(start, count * 2)
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(index, instruction)| {
+                let address = start + index as u16 * 2;
+                let opcode = (u16::from(instruction[0]) << 8) | u16::from(instruction[1]);
+
+                DisassembledInstruction {
+                    address,
+                    opcode,
+                    breakpoint: breakpoints.contains(&address),
+                    current: address == program_counter,
+                    text: disassembler::disassemble(opcode),
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.emulator.is_paused()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused != self.emulator.is_paused() {
+            self.emulator.handle_host_event(HostEvent::TogglePause);
+        }
+    }
+
+    /// Runs the instruction at PC and redraws. Only works while paused.
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        self.emulator.handle_host_event(HostEvent::Step);
+        self.blit()
+    }
+
+    /// Returns whether a breakpoint is now set at `address`.
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        self.emulator.toggle_breakpoint(address)
+    }
+
+    pub fn get_breakpoints(&self) -> Vec<u16> {
+        self.emulator.get_breakpoints().iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod test_game {
+    use super::*;
+
+    #[test]
+    fn test_load_rom_ignores_super_chip_looking_data() {
+        let mut game = Game::new();
+        // Sprite data holding the bytes of 00FF (high resolution) and F075.
+        let rom = [0x12, 0x04, 0x00, 0xff, 0xf0, 0x75, 0x12, 0x04];
+
+        game.load_rom(&rom, "GAME.ch8").unwrap();
+        assert_eq!("chip8", game.get_platform());
+
+        game.load_rom(&rom, "GAME.sc8").unwrap();
+        assert_eq!("schip", game.get_platform());
     }
 }
@@ -0,0 +1,285 @@
+use crate::HostCommand;
+use std::fmt;
+
+/// Commands the page posts to the emulator worker, as `Uint8Array`s so they
+/// can be transferred rather than copied.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Request {
+    LoadRom {
+        file_name: String,
+        rom: Vec<u8>,
+    },
+    SetKeymapLayout(String),
+    SetKeymapConfig(String),
+    Start,
+    Stop,
+    /// A `KeyboardEvent.key` going down or up.
+    Key {
+        name: String,
+        held: bool,
+    },
+    Command(HostCommand),
+}
+
+/// What the worker posts back.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// The game runs, with the platform detected for the ROM.
+    Started(String),
+    /// Workers have no Web Audio, so the page plays the beep.
+    Sound(bool),
+    Halted,
+    Error(String),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ProtocolError {
+    Empty,
+    UnknownTag(u8),
+    Truncated,
+    InvalidText,
+    UnknownCommand(u8),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Empty => write!(formatter, "empty message"),
+            ProtocolError::UnknownTag(tag) => write!(formatter, "unknown message {:#04x}", tag),
+            ProtocolError::Truncated => write!(formatter, "truncated message"),
+            ProtocolError::InvalidText => write!(formatter, "message text is not UTF-8"),
+            ProtocolError::UnknownCommand(command) => {
+                write!(formatter, "unknown host command {}", command)
+            }
+        }
+    }
+}
+
+const COMMANDS: [HostCommand; 10] = [
+    HostCommand::Quit,
+    HostCommand::TogglePause,
+    HostCommand::Reset,
+    HostCommand::SaveState,
+    HostCommand::LoadState,
+    HostCommand::SpeedUp,
+    HostCommand::SlowDown,
+    HostCommand::Redraw,
+    HostCommand::Step,
+    HostCommand::ToggleBreakpoint,
+];
+
+/// Strings are prefixed with their length as a big endian u32; the last
+/// field of a message takes the rest of it instead.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> Result<u8, ProtocolError> {
+        let (byte, rest) = self.bytes.split_first().ok_or(ProtocolError::Truncated)?;
+        self.bytes = rest;
+
+        Ok(*byte)
+    }
+
+    fn read_string(&mut self) -> Result<String, ProtocolError> {
+        if self.bytes.len() < 4 {
+            return Err(ProtocolError::Truncated);
+        }
+        let (length, rest) = self.bytes.split_at(4);
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+        if rest.len() < length {
+            return Err(ProtocolError::Truncated);
+        }
+
+        let (text, rest) = rest.split_at(length);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| ProtocolError::InvalidText)
+    }
+
+    fn read_rest(&mut self) -> Vec<u8> {
+        let rest = self.bytes.to_vec();
+        self.bytes = &[];
+
+        rest
+    }
+
+    fn read_rest_string(&mut self) -> Result<String, ProtocolError> {
+        String::from_utf8(self.read_rest()).map_err(|_| ProtocolError::InvalidText)
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+fn read_tag(bytes: &[u8]) -> Result<(u8, Reader<'_>), ProtocolError> {
+    let mut reader = Reader { bytes };
+    let tag = reader.read_u8().map_err(|_| ProtocolError::Empty)?;
+
+    Ok((tag, reader))
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Request::LoadRom { file_name, rom } => {
+                bytes.push(0);
+                write_string(&mut bytes, file_name);
+                bytes.extend_from_slice(rom);
+            }
+            Request::SetKeymapLayout(layout) => {
+                bytes.push(1);
+                bytes.extend_from_slice(layout.as_bytes());
+            }
+            Request::SetKeymapConfig(config) => {
+                bytes.push(2);
+                bytes.extend_from_slice(config.as_bytes());
+            }
+            Request::Start => bytes.push(3),
+            Request::Stop => bytes.push(4),
+            Request::Key { name, held } => {
+                bytes.push(5);
+                bytes.push(*held as u8);
+                bytes.extend_from_slice(name.as_bytes());
+            }
+            Request::Command(command) => {
+                bytes.push(6);
+                bytes.push(*command as u8);
+            }
+        }
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Request, ProtocolError> {
+        let (tag, mut reader) = read_tag(bytes)?;
+
+        match tag {
+            0 => Ok(Request::LoadRom {
+                file_name: reader.read_string()?,
+                rom: reader.read_rest(),
+            }),
+            1 => Ok(Request::SetKeymapLayout(reader.read_rest_string()?)),
+            2 => Ok(Request::SetKeymapConfig(reader.read_rest_string()?)),
+            3 => Ok(Request::Start),
+            4 => Ok(Request::Stop),
+            5 => Ok(Request::Key {
+                held: reader.read_u8()? != 0,
+                name: reader.read_rest_string()?,
+            }),
+            6 => {
+                let command = reader.read_u8()?;
+                COMMANDS
+                    .get(command as usize)
+                    .map(|command| Request::Command(*command))
+                    .ok_or(ProtocolError::UnknownCommand(command))
+            }
+            _ => Err(ProtocolError::UnknownTag(tag)),
+        }
+    }
+}
+
+impl Event {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Event::Started(platform) => {
+                bytes.push(0);
+                bytes.extend_from_slice(platform.as_bytes());
+            }
+            Event::Sound(on) => {
+                bytes.push(1);
+                bytes.push(*on as u8);
+            }
+            Event::Halted => bytes.push(2),
+            Event::Error(message) => {
+                bytes.push(3);
+                bytes.extend_from_slice(message.as_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Event, ProtocolError> {
+        let (tag, mut reader) = read_tag(bytes)?;
+
+        match tag {
+            0 => Ok(Event::Started(reader.read_rest_string()?)),
+            1 => Ok(Event::Sound(reader.read_u8()? != 0)),
+            2 => Ok(Event::Halted),
+            3 => Ok(Event::Error(reader.read_rest_string()?)),
+            _ => Err(ProtocolError::UnknownTag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_protocol {
+    use super::*;
+
+    #[test]
+    fn test_requests_survive_round_trip() {
+        let requests = vec![
+            Request::LoadRom {
+                file_name: "PONG".to_owned(),
+                rom: vec![0x00, 0xe0, 0x12, 0x00],
+            },
+            Request::SetKeymapLayout("azerty".to_owned()),
+            Request::SetKeymapConfig("[keys]\n1 = 1\n".to_owned()),
+            Request::Start,
+            Request::Stop,
+            Request::Key {
+                name: "ArrowUp".to_owned(),
+                held: true,
+            },
+            Request::Command(HostCommand::ToggleBreakpoint),
+        ];
+
+        for request in requests {
+            assert_eq!(Ok(request.clone()), Request::decode(&request.encode()));
+        }
+    }
+
+    #[test]
+    fn test_events_survive_round_trip() {
+        let events = vec![
+            Event::Started("schip".to_owned()),
+            Event::Sound(true),
+            Event::Halted,
+            Event::Error("no ROM loaded".to_owned()),
+        ];
+
+        for event in events {
+            assert_eq!(Ok(event.clone()), Event::decode(&event.encode()));
+        }
+    }
+
+    #[test]
+    fn test_commands_are_in_declaration_order() {
+        for (index, command) in COMMANDS.iter().enumerate() {
+            assert_eq!(index, *command as usize);
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_messages() {
+        assert_eq!(Err(ProtocolError::Empty), Request::decode(&[]));
+        assert_eq!(
+            Err(ProtocolError::UnknownTag(0x7f)),
+            Request::decode(&[0x7f])
+        );
+        assert_eq!(
+            Err(ProtocolError::Truncated),
+            Request::decode(&[0, 0, 0, 0, 9, b'P'])
+        );
+        assert_eq!(Err(ProtocolError::InvalidText), Event::decode(&[3, 0xff]));
+        assert_eq!(
+            Err(ProtocolError::UnknownCommand(42)),
+            Request::decode(&[6, 42])
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::implementation::audio::{self, BrowserBeeper};
+use crate::protocol::{Event, Request};
+use crate::{Game, HostCommand, RunningGame};
+use chip8::keymap::{parse_key_name, Keymap, KeymapConfig};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+/// Runs a game inside a Web Worker, drawing to an `OffscreenCanvas` so the
+/// emulation keeps its pace whatever the page's main thread is busy with.
+/// The worker script feeds it the messages the page posts, calls `run_frame`
+/// on its own timer and posts back whatever `poll_event` returns.
+#[wasm_bindgen]
+pub struct WorkerHost {
+    game: Game,
+    running_game: Option<RunningGame>,
+    sound_on: bool,
+    events: VecDeque<Event>,
+}
+
+#[wasm_bindgen]
+impl WorkerHost {
+    pub fn new(canvas: JsValue) -> Result<WorkerHost, JsValue> {
+        let mut game = Game::new();
+        game.set_canvas(canvas)?;
+
+        Ok(WorkerHost {
+            game,
+            running_game: None,
+            sound_on: false,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Applies a message encoded by the `encode_*` functions. Failures are
+    /// reported as an error event rather than thrown.
+    pub fn handle_message(&mut self, message: &[u8]) {
+        let result = Request::decode(message)
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+            .and_then(|request| self.handle_request(request));
+
+        if let Err(error) = result {
+            let message = error.as_string().unwrap_or_else(|| format!("{:?}", error));
+            self.events.push_back(Event::Error(message));
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<(), JsValue> {
+        match request {
+            Request::LoadRom { file_name, rom } => self.game.load_rom(&rom, &file_name)?,
+            Request::SetKeymapLayout(layout) => {
+                if !self.game.set_keymap_layout(&layout) {
+                    return Err(JsValue::from_str(&format!("unknown layout `{}`", layout)));
+                }
+            }
+            Request::SetKeymapConfig(config) => self.game.set_keymap_config(&config)?,
+            Request::Start => {
+                self.stop();
+                self.running_game = Some(self.game.start()?);
+                self.events
+                    .push_back(Event::Started(self.game.get_platform()));
+            }
+            Request::Stop => self.stop(),
+            Request::Key { name, held } => {
+                if let Some(running_game) = &mut self.running_game {
+                    running_game.set_key_held(&name, held);
+                }
+            }
+            Request::Command(command) => {
+                if let Some(running_game) = &mut self.running_game {
+                    running_game.send_host_command(command);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.running_game = None;
+        self.set_sound_on(false);
+    }
+
+    fn set_sound_on(&mut self, sound_on: bool) {
+        if self.sound_on != sound_on {
+            self.sound_on = sound_on;
+            self.events.push_back(Event::Sound(sound_on));
+        }
+    }
+
+    /// Same as `RunningGame::run_frame`, doing nothing until a game started.
+    pub fn run_frame(&mut self, elapsed_ms: f64) {
+        let running_game = match &mut self.running_game {
+            Some(running_game) => running_game,
+            None => return,
+        };
+
+        match running_game.run_frame(elapsed_ms) {
+            Ok(true) => {
+                let sound_on = running_game.is_sound_on();
+                self.set_sound_on(sound_on);
+            }
+            Ok(false) => {
+                self.stop();
+                self.events.push_back(Event::Halted);
+            }
+            Err(error) => {
+                self.stop();
+                let message = error.as_string().unwrap_or_else(|| format!("{:?}", error));
+                self.events.push_back(Event::Error(message));
+            }
+        }
+    }
+
+    /// The next message for the page, if any.
+    pub fn poll_event(&mut self) -> Option<Vec<u8>> {
+        self.events.pop_front().map(|event| event.encode())
+    }
+}
+
+#[wasm_bindgen]
+pub fn encode_load_rom(file_name: &str, rom: &[u8]) -> Vec<u8> {
+    Request::LoadRom {
+        file_name: file_name.to_owned(),
+        rom: rom.to_vec(),
+    }
+    .encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_keymap_layout(layout: &str) -> Vec<u8> {
+    Request::SetKeymapLayout(layout.to_owned()).encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_keymap_config(config: &str) -> Vec<u8> {
+    Request::SetKeymapConfig(config.to_owned()).encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_start() -> Vec<u8> {
+    Request::Start.encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_stop() -> Vec<u8> {
+    Request::Stop.encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_key(name: &str, held: bool) -> Vec<u8> {
+    Request::Key {
+        name: name.to_owned(),
+        held,
+    }
+    .encode()
+}
+
+#[wasm_bindgen]
+pub fn encode_host_command(command: HostCommand) -> Vec<u8> {
+    Request::Command(command).encode()
+}
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum WorkerEventKind {
+    Started,
+    Sound,
+    Halted,
+    Error,
+}
+
+/// An event posted by a `WorkerHost`, decoded for the page.
+#[wasm_bindgen]
+pub struct WorkerEvent {
+    pub kind: WorkerEventKind,
+    /// Whether the beep should play, for `Sound` events.
+    pub sound_on: bool,
+    text: String,
+}
+
+#[wasm_bindgen]
+impl WorkerEvent {
+    /// The platform for `Started` events, the message for `Error` events.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub fn decode_worker_event(message: &[u8]) -> Result<WorkerEvent, JsValue> {
+    let event = Event::decode(message).map_err(|error| JsValue::from_str(&error.to_string()))?;
+    let (kind, sound_on, text) = match event {
+        Event::Started(platform) => (WorkerEventKind::Started, false, platform),
+        Event::Sound(sound_on) => (WorkerEventKind::Sound, sound_on, String::new()),
+        Event::Halted => (WorkerEventKind::Halted, false, String::new()),
+        Event::Error(message) => (WorkerEventKind::Error, false, message),
+    };
+
+    Ok(WorkerEvent {
+        kind,
+        sound_on,
+        text,
+    })
+}
+
+/// The keymap a game running in a worker uses, so that the page only takes
+/// the keys bound in it away from the browser.
+#[wasm_bindgen]
+pub struct WorkerKeymap {
+    keymap: Keymap,
+}
+
+#[wasm_bindgen]
+impl WorkerKeymap {
+    pub fn from_layout(layout: &str) -> Result<WorkerKeymap, JsValue> {
+        let keymap = Keymap::from_preset(layout)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown layout `{}`", layout)))?;
+
+        Ok(WorkerKeymap { keymap })
+    }
+
+    /// The config's keymap for `rom_name`, as the worker picks it.
+    pub fn from_config(config: &str, rom_name: &str) -> Result<WorkerKeymap, JsValue> {
+        let config =
+            KeymapConfig::parse(config).map_err(|error| JsValue::from_str(&error.to_string()))?;
+
+        Ok(WorkerKeymap {
+            keymap: config.keymap_for(rom_name).clone(),
+        })
+    }
+
+    /// Whether a `KeyboardEvent.key` is bound to a keypad key or a host command.
+    pub fn is_bound(&self, key_name: &str) -> bool {
+        parse_key_name(key_name)
+            .and_then(|character| self.keymap.get(character))
+            .is_some()
+    }
+}
+
+/// The beep of a game running in a worker, played on the page.
+#[wasm_bindgen]
+pub struct Beeper {
+    beeper: BrowserBeeper,
+}
+
+#[wasm_bindgen]
+impl Beeper {
+    pub fn new(frequency: f32, volume: f32) -> Result<Beeper, JsValue> {
+        Ok(Beeper {
+            beeper: BrowserBeeper::new(
+                audio::clamp_frequency(frequency),
+                audio::clamp_volume(volume),
+            )?,
+        })
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.beeper.set_playing(playing);
+    }
+
+    pub fn set_sound_frequency(&mut self, frequency: f32) {
+        self.beeper.set_frequency(audio::clamp_frequency(frequency));
+    }
+
+    pub fn set_sound_volume(&mut self, volume: f32) {
+        self.beeper.set_volume(audio::clamp_volume(volume));
+    }
+
+    /// Call from a user gesture handler, browsers start audio suspended.
+    pub fn resume_audio(&self) {
+        self.beeper.resume();
+    }
+}
+
+#[cfg(test)]
+mod test_worker {
+    use super::*;
+
+    #[test]
+    fn test_worker_keymap_knows_bound_keys() {
+        let keymap = WorkerKeymap::from_layout("qwerty").unwrap();
+        assert!(keymap.is_bound("x"));
+        assert!(keymap.is_bound("Escape"));
+        assert!(!keymap.is_bound("Tab"));
+        assert!(!keymap.is_bound("F5"));
+
+        let keymap = WorkerKeymap::from_config("[PONG]\nx = none\n", "PONG").unwrap();
+        assert!(!keymap.is_bound("x"));
+        assert!(keymap.is_bound("q"));
+    }
+}